@@ -0,0 +1,208 @@
+#ifndef BATSMT_H
+#define BATSMT_H
+
+/* Declarations of the C API in src/capi.rs, which must be kept in sync. */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+/*
+ * Success.
+ */
+#define BATSMT_OK 0
+
+/*
+ * A pointer argument is null.
+ */
+#define BATSMT_ERR_NULL 1
+
+/*
+ * The context or solver is used by a search running in another thread.
+ */
+#define BATSMT_ERR_BUSY 2
+
+/*
+ * An argument is invalid (not UTF-8, the buffer is too small, no such
+ * term or literal).
+ */
+#define BATSMT_ERR_INVALID 3
+
+/*
+ * The call failed (ill-typed term, wrong arity, …).
+ */
+#define BATSMT_ERR_FAILED 4
+
+/*
+ * The context cannot be freed, some of its solvers are still alive.
+ */
+#define BATSMT_ERR_HAS_SOLVERS 5
+
+/*
+ * Values of literals.
+ */
+#define BATSMT_TRUE 0
+
+#define BATSMT_FALSE 1
+
+#define BATSMT_UNDEF 2
+
+typedef struct batsmt_ctx batsmt_ctx;
+
+typedef struct batsmt_solver batsmt_solver;
+
+/*
+ * A term of some context.
+ */
+typedef uint32_t batsmt_term;
+
+/*
+ * A literal of some solver. The negation of `l` is `-l`.
+ */
+typedef int32_t batsmt_lit;
+
+/*
+ * Message of the last error in the current thread.
+ */
+const char *batsmt_last_error(void);
+
+/*
+ * Create a new context. Returns null on failure.
+ */
+batsmt_ctx *batsmt_ctx_new(void);
+
+/*
+ * Free a context. Its terms must not be used afterwards.
+ * Its solvers must be freed first, or `BATSMT_ERR_HAS_SOLVERS` is returned.
+ */
+int batsmt_ctx_free(batsmt_ctx *c);
+
+/*
+ * The boolean type.
+ */
+int batsmt_ty_bool(batsmt_ctx *c, batsmt_term *res);
+
+/*
+ * The uninterpreted type named `name`. Fails if `name` is a function
+ * symbol.
+ */
+int batsmt_ty_const(batsmt_ctx *c, const char *name, batsmt_term *res);
+
+/*
+ * The term `true` or `false`.
+ */
+int batsmt_term_bool(batsmt_ctx *c, bool b, batsmt_term *res);
+
+/*
+ * Negation of a boolean term.
+ */
+int batsmt_term_not(batsmt_ctx *c, batsmt_term t, batsmt_term *res);
+
+/*
+ * The symbol `name`, with arguments of types `args[0 .. n_args]`
+ * and return type `ret`. Fails if `name` is declared with another
+ * signature.
+ */
+int batsmt_term_const(batsmt_ctx *c,
+                      const char *name,
+                      const batsmt_term *args,
+                      uintptr_t n_args,
+                      batsmt_term ret,
+                      batsmt_term *res);
+
+/*
+ * Same as `batsmt_term_const`, for a datatype constructor.
+ */
+int batsmt_term_cstor(batsmt_ctx *c,
+                      const char *name,
+                      const batsmt_term *args,
+                      uintptr_t n_args,
+                      batsmt_term ret,
+                      batsmt_term *res);
+
+/*
+ * Declare that the symbol `t` is a datatype constructor.
+ */
+int batsmt_term_set_cstor(batsmt_ctx *c, batsmt_term t);
+
+/*
+ * Equality of two terms of the same type.
+ */
+int batsmt_term_eq(batsmt_ctx *c, batsmt_term t1, batsmt_term t2, batsmt_term *res);
+
+/*
+ * Application of the symbol `f` to `args[0 .. n_args]`.
+ */
+int batsmt_term_app(batsmt_ctx *c,
+                    batsmt_term f,
+                    const batsmt_term *args,
+                    uintptr_t n_args,
+                    batsmt_term *res);
+
+/*
+ * Create a solver for terms of `c`.
+ */
+int batsmt_solver_new(batsmt_ctx *c, batsmt_solver **res);
+
+/*
+ * Free a solver.
+ */
+int batsmt_solver_free(batsmt_solver *s);
+
+/*
+ * Set the options of the solver (see `Batsmt.Solver.options`).
+ */
+int batsmt_solver_set_options(batsmt_solver *s, uint64_t seed, bool auto_simplify,
+                              bool keep_clauses);
+
+/*
+ * A fresh literal.
+ */
+int batsmt_solver_new_lit(batsmt_solver *s, batsmt_lit *res);
+
+/*
+ * The literal of the boolean term `t`.
+ */
+int batsmt_solver_new_term_lit(batsmt_solver *s, batsmt_ctx *c, batsmt_term t, batsmt_lit *res);
+
+/*
+ * Add the clause `lits[0 .. n_lits]`. Fails with `BATSMT_ERR_INVALID`
+ * if a literal was not created by `s`.
+ */
+int batsmt_solver_add_clause(batsmt_solver *s, const batsmt_lit *lits, uintptr_t n_lits);
+
+/*
+ * Add the clauses in `lits[0 .. n_lits]`, each followed by `0`.
+ * Fails with `BATSMT_ERR_INVALID`, adding nothing, if a literal was
+ * not created by `s`.
+ */
+int batsmt_solver_add_clauses(batsmt_solver *s, const batsmt_lit *lits, uintptr_t n_lits);
+
+/*
+ * Add an assumption for the next call to `batsmt_solver_solve`.
+ */
+int batsmt_solver_add_assumption(batsmt_solver *s, batsmt_lit l);
+
+/*
+ * Check satisfiability under the current assumptions, which are then cleared.
+ */
+int batsmt_solver_solve(batsmt_solver *s, batsmt_ctx *c, bool *res);
+
+/*
+ * Value of `l` in the model: `BATSMT_TRUE`, `BATSMT_FALSE` or `BATSMT_UNDEF`.
+ */
+int batsmt_solver_value(batsmt_solver *s, batsmt_lit l, int *res);
+
+/*
+ * Copy the unsat core of the last call to `batsmt_solver_solve` into
+ * `buf[0 .. cap]`, and its size into `*n`. If `cap` is too small,
+ * only `*n` is set and `BATSMT_ERR_INVALID` is returned.
+ */
+int batsmt_solver_unsat_core(batsmt_solver *s, batsmt_lit *buf, uintptr_t cap, uintptr_t *n);
+
+/*
+ * Is `l` in the unsat core of the last call to `batsmt_solver_solve`?
+ */
+int batsmt_solver_unsat_core_contains(batsmt_solver *s, batsmt_lit l, bool *res);
+
+#endif /* BATSMT_H */
@@ -1,4 +1,6 @@
 
+type term = int
+
 module Ctx = struct
   type t
 
@@ -7,6 +9,19 @@ module Ctx = struct
   let create() : t =
     let c = create_ () in
     c
+
+  external decls_to_smt2 : t -> string = "ml_batsmt_ctx_decls_to_smt2"
+
+  type stats = {
+    n_terms: int;
+    n_syms: int;
+    n_cstors: int;
+    mem_size: int;
+  }
+
+  external stats : t -> stats = "ml_batsmt_ctx_stats"
+  external dump : t -> string = "ml_batsmt_ctx_dump"
+  external compact : t -> term array -> t * term array = "ml_batsmt_ctx_compact"
 end
 
 module Lit = struct
@@ -45,8 +60,8 @@ module Ty = struct
   let hash (a:t) = Hashtbl.hash a
   let compare (a:t) b = Pervasives.compare a b
 
-  external bool_ : Ctx.t -> t = "ml_batsmt_ty_bool" [@@noalloc]
-  external const_ : Ctx.t -> string -> t = "ml_batsmt_ty_const" [@@noalloc]
+  external bool_ : Ctx.t -> t = "ml_batsmt_ty_bool"
+  external const_ : Ctx.t -> string -> t = "ml_batsmt_ty_const"
 
   let mk_bool = bool_
   let mk_str = const_
@@ -57,29 +72,24 @@ module Ty = struct
 end
 
 module Term = struct
-  type t = int
+  type t = term
 
   let id (t:t) : t = t
   let equal (a:t) b = a=b
   let hash (a:t) = Hashtbl.hash a
   let compare (a:t) b = Pervasives.compare a b
 
-  external const_ : Ctx.t -> string -> Ty.t array -> Ty.t -> t = "ml_batsmt_term_const" [@@noalloc]
-  external app_fun_ : Ctx.t -> t -> unit = "ml_batsmt_term_app_fun" [@@noalloc]
-  external app_arg_ : Ctx.t -> t -> unit = "ml_batsmt_term_app_arg" [@@noalloc]
-  external app_finalize_ : Ctx.t -> t = "ml_batsmt_term_app_finalize" [@@noalloc]
-  external select_ : Ctx.t -> t -> int -> t -> t = "ml_batsmt_term_select" [@@noalloc]
-  external set_cstor_: Ctx.t -> t -> unit = "ml_batsmt_term_set_cstor" [@@noalloc]
-  external bool_ : Ctx.t -> bool -> t = "ml_batsmt_term_bool" [@@noalloc]
-  external not_ : Ctx.t -> t -> t = "ml_batsmt_term_not" [@@noalloc]
-  external eq_ : Ctx.t -> t -> t -> t = "ml_batsmt_term_eq" [@@noalloc]
-
-  external kind : Ctx.t -> t -> int = "ml_batsmt_term_kind" [@@noalloc]
-  external get_cst_name_ : Ctx.t -> t -> string = "ml_batsmt_term_get_const_name"
-  external get_bool_ : Ctx.t -> t -> bool = "ml_batsmt_term_get_bool" [@@noalloc]
-  external get_app_fun_ : Ctx.t -> t -> t = "ml_batsmt_term_get_app_fun" [@@noalloc]
-  external get_app_n_args_ : Ctx.t -> t -> int = "ml_batsmt_term_get_app_n_args" [@@noalloc]
-  external get_app_nth_arg_ : Ctx.t -> t -> int -> t = "ml_batsmt_term_get_app_nth_arg" [@@noalloc]
+  external const_ : Ctx.t -> string -> Ty.t array -> Ty.t -> t = "ml_batsmt_term_const"
+  external app_ : Ctx.t -> t -> t array -> t = "ml_batsmt_term_app"
+  external import : Ctx.t -> string array -> int array -> t array = "ml_batsmt_term_import"
+  external select_ : Ctx.t -> t -> int -> t -> t = "ml_batsmt_term_select"
+  external cstor_ : Ctx.t -> string -> Ty.t array -> Ty.t -> t = "ml_batsmt_term_cstor"
+  external bool_ : Ctx.t -> bool -> t = "ml_batsmt_term_bool"
+  external not_ : Ctx.t -> t -> t = "ml_batsmt_term_not"
+  external eq_ : Ctx.t -> t -> t -> t = "ml_batsmt_term_eq"
+
+  external view_ : Ctx.t -> t -> int * t * t array * string = "ml_batsmt_term_view"
+  external subterms : Ctx.t -> t -> t array = "ml_batsmt_term_subterms"
   external get_select_ : Ctx.t -> t -> (t * int * t) = "ml_batsmt_term_get_select"
 
   let mk_bool = bool_
@@ -87,10 +97,7 @@ module Term = struct
   let mk_not = not_
   let mk_const ctx s args ret : t = const_ ctx s (Array.of_list args) ret
 
-  let mk_cstor ctx s args ret : t =
-    let c = mk_const ctx s args ret in
-    set_cstor_ ctx c;
-    c
+  let mk_cstor ctx s args ret : t = cstor_ ctx s (Array.of_list args) ret
 
   let[@inline] mk_select ctx ~cstor idx u : t =
     select_ ctx cstor idx u
@@ -99,17 +106,11 @@ module Term = struct
     match l with
     | [] -> f
     | _ ->
-      app_fun_ ctx f;
-      List.iter (app_arg_ ctx) l;
-      app_finalize_ ctx
+      app_ ctx f (Array.of_list l)
 
   let app_a ctx f a =
     if Array.length a = 0 then f
-    else (
-      app_fun_ ctx f;
-      Array.iter (app_arg_ ctx) a;
-      app_finalize_ ctx
-    )
+    else app_ ctx f a
 
   type view =
     | Bool of bool
@@ -123,35 +124,18 @@ module Term = struct
       }
     | Not of t
 
-  let list_init n f =
-    let rec aux i n f =
-      if i=n then []
-      else f i :: aux (i+1) n f
-    in aux 0 n f
-
   let view (ctx:Ctx.t) (t:t) : view =
     (* NOTE: keep in sync with `ctx.rs: AstKind` *)
-    match kind ctx t with
-    | 0 -> (* bool *)
-      let b = get_bool_ ctx t in
-      Bool b
-    | 1 -> (* app *)
-      let f = get_app_fun_ ctx t in
-      let n = get_app_n_args_ ctx t in
-      let args = list_init n (get_app_nth_arg_ ctx t) in
-      App (f, args)
-    | 2 -> (* const *)
-      let s = get_cst_name_ ctx t in
-      Cst_unin s
-    | 3 -> (* cstor *)
-      let s = get_cst_name_ ctx t in
-      Cst_cstor s
+    let k, head, args, name = view_ ctx t in
+    match k with
+    | 0 -> (* bool *) Bool (head <> 0)
+    | 1 -> (* app *) App (head, Array.to_list args)
+    | 2 -> (* const *) Cst_unin name
+    | 3 -> (* cstor *) Cst_cstor name
     | 4 -> (* select *)
       let c, idx, sub = get_select_ ctx t in
       Select {c; idx; sub}
-    | 5 -> (* not *)
-      let u = get_app_nth_arg_ ctx t 0 in
-      Not u
+    | 5 -> (* not *) Not args.(0)
     | n -> failwith ("invalid term kind "^ string_of_int n)
 
   let pp ctx (out) t =
@@ -173,6 +157,10 @@ module Term = struct
     in
     pp out t
 
+  external to_smt2 : Ctx.t -> t -> string = "ml_batsmt_term_to_smt2"
+
+  let pp_smt2 ctx out t = Format.pp_print_string out (to_smt2 ctx t)
+
   let __undef = max_int
 end
 
@@ -185,29 +173,43 @@ exception E_unsat
 module Solver = struct
   type t
 
-  external create_ : Ctx.t -> t = "ml_batsmt_solver_new"
-  external mk_lit_ : t -> Lit.t = "ml_batsmt_solver_new_lit" [@@noalloc]
-  external mk_term_lit_ : t -> Ctx.t -> Term.t -> Lit.t = "ml_batsmt_solver_new_term_lit" [@@noalloc]
-  external push_assumption_ : t -> Lit.t -> unit = "ml_batsmt_solver_add_assumption" [@@noalloc]
-  external push_clause_lit_ : t -> Lit.t -> unit = "ml_batsmt_solver_add_clause_lit" [@@noalloc]
-  external add_clause_ : t -> unit = "ml_batsmt_solver_add_clause" [@@noalloc]
-  external solve_ : t -> Ctx.t -> bool = "ml_batsmt_solver_solve" [@@noalloc]
+  type options = {
+    seed: int;
+    auto_simplify: bool;
+    keep_clauses: bool;
+  }
+
+  let default_options = { seed=0; auto_simplify=false; keep_clauses=false; }
+
+  external create_ : Ctx.t -> int -> bool -> bool -> t = "ml_batsmt_solver_new"
+  external set_options_ : t -> int -> bool -> bool -> unit = "ml_batsmt_solver_set_options"
+  external mk_lit_ : t -> Lit.t = "ml_batsmt_solver_new_lit"
+  external mk_term_lit_ : t -> Ctx.t -> Term.t -> Lit.t = "ml_batsmt_solver_new_term_lit"
+  external push_assumption_ : t -> Lit.t -> unit = "ml_batsmt_solver_add_assumption"
+  external add_clause_a_ : t -> Lit.t array -> unit = "ml_batsmt_solver_add_clause_a"
+  external add_clauses :
+    t -> (int32, Bigarray.int32_elt, Bigarray.c_layout) Bigarray.Array1.t -> unit
+    = "ml_batsmt_solver_add_clauses"
+  external solve_ : t -> Ctx.t -> bool = "ml_batsmt_solver_solve"
   external unsat_core_ : t -> Lit.t array = "ml_batsmt_solver_unsat_core"
-  external unsat_core_contains_ : t -> Lit.t -> bool = "ml_batsmt_solver_unsat_core_contains" [@@noalloc]
-  external value_lvl_0_ : t -> Lit.t -> int = "ml_batsmt_solver_value_lvl_0" [@@noalloc]
-  external value_ : t -> Lit.t -> int = "ml_batsmt_solver_value" [@@noalloc]
-
-  let create (ctx:Ctx.t) : t =
-    let s = create_ ctx in
+  external unsat_core_contains_ : t -> Lit.t -> bool = "ml_batsmt_solver_unsat_core_contains"
+  external value_lvl_0_ : t -> Lit.t -> int = "ml_batsmt_solver_value_lvl_0"
+  external value_ : t -> Lit.t -> int = "ml_batsmt_solver_value"
+  external make_term_lits : t -> Ctx.t -> Term.t array -> Lit.t array = "ml_batsmt_solver_new_term_lits"
+  external values : t -> Lit.t array -> Lbool.t array = "ml_batsmt_solver_values"
+  external model_bits : t -> string = "ml_batsmt_solver_model_bits"
+
+  let create ?(options=default_options) (ctx:Ctx.t) : t =
+    let s = create_ ctx options.seed options.auto_simplify options.keep_clauses in
     s
 
+  let set_options (s:t) (o:options) : unit =
+    set_options_ s o.seed o.auto_simplify o.keep_clauses
+
   let add_clause_l (s:t) (c: Lit.t list) : unit =
-    List.iter (push_clause_lit_ s) c;
-    add_clause_ s
+    add_clause_a_ s (Array.of_list c)
 
-  let add_clause_a (s:t) (c: Lit.t array) : unit =
-    Array.iter (push_clause_lit_ s) c;
-    add_clause_ s
+  let add_clause_a = add_clause_a_
 
   let make_lit = mk_lit_
   let make_term_lit = mk_term_lit_
@@ -240,6 +242,57 @@ module Solver = struct
     let is_sat = solve_ s ctx in
     if is_sat then () else raise E_unsat
 
+  type job
+  type handle = {
+    job: job;
+    solver: t; (* keep the solver and context alive while the job runs *)
+    ctx: Ctx.t;
+  }
+
+  type status =
+    | Running
+    | Done of res
+    | Detached
+
+  external solve_async_ : t -> Ctx.t -> job = "ml_batsmt_solver_solve_async"
+  external job_poll_ : job -> int = "ml_batsmt_job_poll"
+  external job_wait_ : job -> float -> int = "ml_batsmt_job_wait"
+  external job_detach_ : job -> unit = "ml_batsmt_job_detach"
+
+  let status_of_int_ = function
+    | 0 -> Running
+    | 1 -> Done Sat
+    | 2 -> Done Unsat
+    | 3 -> Detached
+    | 4 -> failwith "batsmt: background search failed"
+    | _ -> assert false
+
+  let solve_async ?(assumptions=[]) (s:t) (ctx:Ctx.t) : handle =
+    List.iter (push_assumption_ s) assumptions;
+    let job = solve_async_ s ctx in
+    {job; solver=s; ctx}
+
+  let poll (h:handle) : status = job_poll_ h.job |> status_of_int_
+
+  let wait ?(timeout= -1.) (h:handle) : status =
+    job_wait_ h.job timeout |> status_of_int_
+
+  let detach (h:handle) : unit = job_detach_ h.job
+
+  type portfolio_result = {
+    result: res;
+    winner: int;
+    core: Lit.t array;
+  }
+
+  external solve_portfolio_ : t -> Ctx.t -> int array -> bool * int * Lit.t array
+    = "ml_batsmt_solver_solve_portfolio"
+
+  let solve_portfolio ?(assumptions=[]) ~seeds (s:t) (ctx:Ctx.t) : portfolio_result =
+    List.iter (push_assumption_ s) assumptions;
+    let is_sat, winner, core = solve_portfolio_ s ctx (Array.of_list seeds) in
+    { result=if is_sat then Sat else Unsat; winner; core; }
+
   let unsat_core = unsat_core_
   let unsat_core_contains = unsat_core_contains_
 
@@ -252,15 +305,69 @@ module Solver = struct
   let value_lvl_0 (s:t) (lit: Lit.t) : Lbool.t = value_lvl_0_ s lit |> lbool_of_int_
   let value (s:t) (lit: Lit.t) : Lbool.t = value_ s lit |> lbool_of_int_
 
-  external n_lits: t -> int = "ml_batsmt_nlits" [@@noalloc]
-  external n_clauses : t -> int = "ml_batsmt_nclauses" [@@noalloc]
-  external n_conflicts : t -> int = "ml_batsmt_nconflicts" [@@noalloc]
-  external n_decisions : t -> int = "ml_batsmt_ndecisions" [@@noalloc]
-  external n_props : t -> int = "ml_batsmt_nprops" [@@noalloc]
+  external to_smt2 : t -> Ctx.t -> string = "ml_batsmt_solver_to_smt2"
+
+  let dump_smt2 (s:t) (ctx:Ctx.t) (oc:out_channel) : unit =
+    output_string oc (to_smt2 s ctx);
+    flush oc
+
+  external load_dimacs : t -> string -> Lit.t array = "ml_batsmt_solver_load_dimacs"
+  external to_dimacs : t -> Ctx.t -> string = "ml_batsmt_solver_to_dimacs"
+
+  let write_dimacs (s:t) (ctx:Ctx.t) (oc:out_channel) : unit =
+    output_string oc (to_dimacs s ctx);
+    flush oc
+
+  external n_lits: t -> int = "ml_batsmt_nlits"
+  external n_clauses : t -> int = "ml_batsmt_nclauses"
+  external n_conflicts : t -> int = "ml_batsmt_nconflicts"
+  external n_decisions : t -> int = "ml_batsmt_ndecisions"
+  external n_props : t -> int = "ml_batsmt_nprops"
+
+  type stats = {
+    n_lits: int;
+    n_clauses: int;
+    n_decisions: int;
+    n_props: int;
+    n_conflicts: int;
+    n_solve: int;
+    solve_time: float;
+  }
+
+  external stats : t -> stats = "ml_batsmt_solver_stats"
+
+  let stats_to_json (s:stats) : string =
+    Printf.sprintf
+      "{\"n_lits\":%d,\"n_clauses\":%d,\"n_decisions\":%d,\"n_props\":%d,\
+       \"n_conflicts\":%d,\"n_solve\":%d,\"solve_time\":%.6f}"
+      s.n_lits s.n_clauses s.n_decisions s.n_props
+      s.n_conflicts s.n_solve s.solve_time
+
+  external n_proved_lvl_0 : t -> int = "ml_batsmt_solver_n_proved_lvl_0"
+  external proved_lvl_0 : t -> int -> Lit.t = "ml_batsmt_solver_proved_lvl_0"
+  external proved_lvl_0_all : t -> Lit.t array = "ml_batsmt_solver_proved_lvl_0_all"
+  external new_proved_lvl_0 : t -> Lit.t array = "ml_batsmt_solver_new_proved_lvl_0"
+end
+
+module Smtlib = struct
+  external parse_and_run : Ctx.t -> string -> string = "ml_batsmt_smtlib_parse_and_run"
+end
+
+module Trace = struct
+  external start : string -> unit = "ml_batsmt_trace_start"
+  external stop : unit -> unit = "ml_batsmt_trace_stop"
+  external replay : string -> int = "ml_batsmt_trace_replay"
+  external minimize_ : string -> string = "ml_batsmt_trace_minimize"
 
-  external n_proved_lvl_0 : t -> int = "ml_batsmt_solver_n_proved_lvl_0" [@@noalloc]
-  external proved_lvl_0 : t -> int -> Lit.t = "ml_batsmt_solver_proved_lvl_0" [@@noalloc]
+  let minimize file = match minimize_ file with
+    | "" -> None
+    | s -> Some s
 end
 
 external log_ : string -> unit = "ml_batsmt_set_log_lvl"
 let set_log_lvl = log_
+
+external peak_mem_ : unit -> int = "ml_batsmt_peak_mem"
+let peak_mem () = match peak_mem_ () with
+  | n when n < 0 -> None
+  | n -> Some n
@@ -1,8 +1,55 @@
 
+type term
+(** Same as {!Term.t}, declared before {!Ctx} for {!Ctx.compact}.
+
+    Functions taking a context and terms raise [Failure] if a term does not
+    exist in that context. A term of another context is only detected if
+    its index is out of range, so mixing contexts is still an error. *)
+
 module Ctx : sig
   type t
+  (** A context. Like solvers and search jobs, contexts are compared and
+      hashed by identity by [compare] and [Hashtbl.hash], and cannot be
+      marshalled. The GC is told about the memory they use. *)
 
   val create : unit -> t
+
+  val decls_to_smt2 : t -> string
+  (** SMT-LIB declarations of every sort and symbol of the context.
+      Sorts with constructors are declared as datatypes, with generated
+      selector names that do not clash with the context's symbols.
+      @raise Failure if a name cannot be printed (see {!Term.to_smt2}). *)
+
+  type stats = {
+    n_terms: int; (** hash-consed terms, including types and symbols *)
+    n_syms: int; (** declared types and symbols *)
+    n_cstors: int; (** datatype constructors *)
+    mem_size: int; (** estimated heap usage, in bytes *)
+  }
+
+  val stats : t -> stats
+
+  val dump : t -> string
+  (** Human-readable dump of the statistics, symbols, and every term
+      of the context, for debugging. Each term is printed on one line as
+      its head and the indices of its arguments, such as [#12: f #7 #9]. *)
+
+  val compact : t -> term array -> t * term array
+  (** [compact ctx roots] copies [roots], and the terms and symbols
+      they need, into a fresh context [ctx'], and returns [ctx'] with
+      the copy of each root.
+
+      This is the way to reclaim dead terms, since a context never forgets
+      a term: once [ctx] and its solvers are no longer used, the GC frees
+      them, and only the live terms remain in [ctx']. Terms are not
+      reclaimed in place: there are no weak term handles, and the terms of
+      live solvers are not kept. Solvers of [ctx] cannot be moved to
+      [ctx']; new ones must be created on it.
+
+      Every term of [ctx] that is not in [roots] is invalid in [ctx'],
+      even if it is reachable from a root: use the returned copies. An
+      old term given to [ctx'] raises [Failure] if its index is out of
+      range, and otherwise silently denotes an unrelated term. *)
 end
 
 module Lit : sig
@@ -30,6 +77,9 @@ module Ty : sig
 
   val mk_bool : Ctx.t -> t
   val mk_str : Ctx.t -> string -> t
+  (** [mk_str ctx name] declares the type [name], or returns it if it
+      already exists.
+      @raise Failure if [name] is a function symbol *)
 
   type view =
     | Bool
@@ -40,7 +90,7 @@ module Ty : sig
 end
 
 module Term : sig
-  type t
+  type t = term
 
   val id: t -> int
 
@@ -49,13 +99,40 @@ module Term : sig
   val compare : t -> t -> int
 
   val mk_const : Ctx.t -> string -> Ty.t list -> Ty.t -> t
+  (** [mk_const ctx name args ret] declares the symbol [name], or returns it
+      if it is already declared with the same signature.
+      @raise Failure if [name] is declared with another signature or as a type *)
+
   val mk_cstor : Ctx.t -> string -> Ty.t list -> Ty.t -> t
+  (** Same as {!mk_const}, for a datatype constructor.
+      @raise Failure also if [name] is a plain constant *)
+
   val mk_select: Ctx.t -> cstor:t -> int -> t -> t
   val mk_bool : Ctx.t -> bool -> t
   val mk_eq : Ctx.t -> t -> t -> t
   val mk_not : Ctx.t -> t -> t
   val app_l : Ctx.t -> t -> t list -> t
+  (** [app_l ctx f l] applies the symbol [f] to [l].
+      @raise Failure if [f] is not a function symbol, or if the number or
+      types of arguments do not match its signature *)
+
   val app_a : Ctx.t -> t -> t array -> t
+  (** Same as {!app_l}. *)
+
+  val import : Ctx.t -> string array -> int array -> t array
+  (** [import ctx names nodes] builds a whole DAG in one call, and returns
+      the term of each node. [nodes] is a sequence of nodes
+      [kind sym n c_1 … c_n], where [sym] is an index in [names]
+      and the children [c_i] are indices of earlier nodes. Kinds are:
+      - [0]: boolean, [sym] is [0] (false) or [1] (true), no children
+      - [1]: the boolean type, no children
+      - [2]: uninterpreted type named [names.(sym)], no children
+      - [3] (resp. [4]): constant (resp. constructor) named [names.(sym)];
+        children are the types of its arguments, then its return type
+      - [5]: negation of the child
+      - [6]: equality of the two children
+      - [7]: application of the first child to the other ones
+      @raise Failure if the encoding is invalid or a term is ill-typed *)
 
   type view =
     | Bool of bool
@@ -71,6 +148,11 @@ module Term : sig
 
   val view : Ctx.t -> t -> view
 
+  val subterms : Ctx.t -> t -> t array
+  (** [subterms ctx t] is the DAG of [t] in topological order:
+      every subterm of [t] (including [t], last) appears once, after
+      its arguments. Function symbols of applications are not included. *)
+
   (* FIXME:
   val ty : Ctx.t -> t -> Ty.t
      *)
@@ -78,6 +160,13 @@ module Term : sig
   (** Printing, based on {!view} *)
   val pp : Ctx.t -> Format.formatter -> t -> unit
 
+  val to_smt2 : Ctx.t -> t -> string
+  (** SMT-LIB representation, using [let] for shared subterms.
+      @raise Failure if a name contains [|] or [\\], which SMT-LIB
+      symbols cannot. *)
+
+  val pp_smt2 : Ctx.t -> Format.formatter -> t -> unit
+
   (* TODO
      - selectors
    *)
@@ -103,10 +192,44 @@ exception E_unsat
 module Solver : sig
   type t
 
-  val create : Ctx.t -> t
+  (** Options of a solver. The SAT core itself cannot be configured. *)
+  type options = {
+    seed: int;
+    (** if non-zero, shuffle the literals of each new clause using this seed.
+        This changes which literals are watched, and hence the search. *)
+
+    auto_simplify: bool;
+    (** call {!simplify} at the beginning of each {!solve} *)
+
+    keep_clauses: bool;
+    (** keep a copy of each new clause. {!solve_portfolio}, {!to_smt2}
+        and {!to_dimacs} need every clause, and raise [Failure] if some
+        were added while this was [false] *)
+  }
+
+  val default_options : options
+
+  val create : ?options:options -> Ctx.t -> t
+  (** [create ctx] makes a new solver for terms of [ctx]. The solver keeps
+      [ctx] alive; every function below that takes a context must be
+      given this one, or it raises [Failure]. *)
+
+  val set_options : t -> options -> unit
+  (** Change options; they are taken into account from the next
+      call to {!solve} (or {!add_clause_l}, for the seed). *)
 
   val add_clause_l : t -> Lit.t list -> unit
   val add_clause_a : t -> Lit.t array -> unit
+  (** Add a clause.
+      @raise Failure if a literal was not created by this solver. *)
+
+  val add_clauses :
+    t -> (int32, Bigarray.int32_elt, Bigarray.c_layout) Bigarray.Array1.t -> unit
+  (** [add_clauses s a] adds many clauses in one call. [a] contains
+      literals (as given by {!Lit.to_int}), each clause being followed by [0];
+      the last [0] can be omitted.
+      @raise Failure if a literal was not created by this solver, in
+      which case no clause is added. *)
 
   val make_lit : t -> Lit.t
   (** Make a pure boolean literal *)
@@ -114,6 +237,10 @@ module Solver : sig
   val make_term_lit : t -> Ctx.t -> Term.t -> Lit.t
   (** Make a literal associated with the given term *)
 
+  val make_term_lits : t -> Ctx.t -> Term.t array -> Lit.t array
+  (** [make_term_lits s ctx a] is [Array.map (make_term_lit s ctx) a],
+      in one call *)
+
   val simplify : t -> res
   (** Boolean simplification *)
 
@@ -123,6 +250,11 @@ module Solver : sig
 
   val solve_a : ?assumptions:Lit.t array -> t -> Ctx.t -> res
   val solve : ?assumptions:Lit.t list -> t -> Ctx.t -> res
+  (** Solve the current set of clauses under the given assumptions.
+
+      The OCaml runtime lock is released during the search, so other
+      threads can run. Meanwhile, using the solver or the context
+      from another thread raises [Failure]. *)
 
   val solve_exn_a : ?assumptions:Lit.t array -> t -> Ctx.t -> unit
   (** Same as {!solve_a}, but @raise E_unsat if unsat *)
@@ -130,6 +262,58 @@ module Solver : sig
   val solve_exn : ?assumptions:Lit.t list -> t -> Ctx.t -> unit
   (** Same as {!solve}, but @raise E_unsat if unsat *)
 
+  type handle
+  (** A search running in the background. *)
+
+  type status =
+    | Running
+    | Done of res
+    | Detached (** see {!detach} *)
+
+  val solve_async : ?assumptions:Lit.t list -> t -> Ctx.t -> handle
+  (** Start solving on a separate thread, and return immediately.
+      The solver and context cannot be used until the search is over
+      (see {!solve}). The handle keeps the solver alive until {!poll}
+      or {!wait} sees the search finish. *)
+
+  val poll : handle -> status
+  (** Current status of the search, without blocking.
+      @raise Failure if the search failed. *)
+
+  val wait : ?timeout:float -> handle -> status
+  (** Wait for the search to finish, or for [timeout] seconds to pass.
+      The OCaml runtime lock is released while waiting.
+      @raise Failure if the search failed. *)
+
+  val detach : handle -> unit
+  (** Stop waiting for the search: {!poll} and {!wait} return [Detached]
+      from now on. This does not stop the search, which cannot be
+      interrupted: it keeps its thread and uses CPU until it completes,
+      and the solver stays unusable until then. *)
+
+  type portfolio_result = {
+    result: res;
+    winner: int; (** index of the seed whose worker answered first *)
+    core: Lit.t array; (** unsat core, if [result = Unsat] *)
+  }
+
+  val solve_portfolio :
+    ?assumptions:Lit.t list -> seeds:int list -> t -> Ctx.t -> portfolio_result
+  (** [solve_portfolio ~seeds s ctx] copies the clauses and literals of [s]
+      into one fresh solver per seed, runs them in parallel threads,
+      and returns the first answer. [s] must keep its clauses
+      (see {!options}).
+      A non-zero seed shuffles clauses and literals so that workers explore
+      the problem differently; seed [0] keeps the original order. Every
+      other worker also flips [auto_simplify].
+
+      [s] itself is not modified, so {!unsat_core} and {!value} do not
+      reflect the result. The remaining workers are stopped if they have
+      not started searching yet; a search cannot be interrupted, so the
+      others finish in the background. At most 64 workers are alive at
+      once, over all calls: when that many are still searching, starting
+      a new one waits for one of them to finish. *)
+
   val unsat_core : t -> Lit.t array
   val unsat_core_contains : t -> Lit.t -> bool
   val value_lvl_0 : t -> Lit.t -> Lbool.t
@@ -137,14 +321,133 @@ module Solver : sig
   (** Value in the model *)
   val value : t -> Lit.t -> Lbool.t
 
+  val values : t -> Lit.t array -> Lbool.t array
+  (** Values of many literals in the model, in one call *)
+
+  val model_bits : t -> string
+  (** The whole model as a bitset: for each variable [v > 0]
+      (see {!Lit.to_int}), bit [v land 7] of byte [v lsr 3] is set
+      iff [v] is true in the model. Variables beyond the end of the
+      string are not true. *)
+
+  val to_smt2 : t -> Ctx.t -> string
+  (** The whole problem as a SMT-LIB script: declarations, one assertion
+      per clause, and a [check-sat-assuming] with the assumptions of the
+      last call to {!solve}. Literals from {!make_lit} are declared as
+      fresh boolean constants, and subterms shared by several literals
+      are named once with [define-fun]. Generated names do not clash
+      with the context's symbols.
+      @raise Failure if a name cannot be printed (see {!Term.to_smt2}). *)
+
+  val dump_smt2 : t -> Ctx.t -> out_channel -> unit
+  (** Write {!to_smt2} on the given channel *)
+
+  val load_dimacs : t -> string -> Lit.t array
+  (** [load_dimacs s file] adds the clauses of the DIMACS CNF [file] to [s],
+      using a fresh literal for each variable. Returns these literals:
+      variable [i] of the file is mapped to index [i-1].
+      If the file has a [p cnf] header, its clauses must match it.
+      @raise Failure if the file cannot be read or parsed, in which
+      case [s] is left unchanged. *)
+
+  val to_dimacs : t -> Ctx.t -> string
+  (** The clauses of the solver in DIMACS CNF, with its own variable
+      numbers. Comments map term literals back to their term (printed as
+      in {!to_smt2}), and list the assumptions of the last {!solve}. *)
+
+  val write_dimacs : t -> Ctx.t -> out_channel -> unit
+  (** Write {!to_dimacs} on the given channel *)
+
   val n_proved_lvl_0 : t -> int
   val proved_lvl_0 : t -> int -> Lit.t
+  (** [proved_lvl_0 s i] is the [i]-th literal proved at level 0.
+      @raise Invalid_argument unless [0 <= i < n_proved_lvl_0 s] *)
+
+  val proved_lvl_0_all : t -> Lit.t array
+  (** All the literals proved at level 0, in one call *)
+
+  val new_proved_lvl_0 : t -> Lit.t array
+  (** Literals proved at level 0 since the previous call to
+      [new_proved_lvl_0] (all of them, the first time). Useful to
+      harvest new units after each {!solve} or {!simplify}. *)
 
   val n_lits : t -> int
   val n_clauses : t -> int
   val n_conflicts : t -> int
   val n_decisions: t -> int
   val n_props : t -> int
+
+  type stats = {
+    n_lits: int;
+    n_clauses: int;
+    n_decisions: int;
+    n_props: int;
+    n_conflicts: int;
+    n_solve: int; (** calls to {!solve} *)
+    solve_time: float; (** time spent in {!solve}, in seconds *)
+  }
+  (** Statistics of the SAT core and of calls to {!solve}. *)
+
+  val stats : t -> stats
+  (** All the statistics, in one call *)
+
+  val stats_to_json : stats -> string
+  (** JSON object with the same fields as {!stats} *)
+end
+
+module Smtlib : sig
+  val parse_and_run : Ctx.t -> string -> string
+  (** [parse_and_run ctx script] runs the SMT-LIB 2.6 script [script]
+      on a fresh solver using [ctx], and returns what the solver printed.
+
+      Supported: [declare-sort], [declare-const], [declare-fun],
+      [define-fun] (without parameters),
+      [declare-datatypes] (without selectors nor testers), [assert]
+      (with [:named]), [check-sat], [check-sat-assuming], [push], [pop],
+      [get-model] and [get-unsat-core]. In models, values of uninterpreted
+      sorts are abstract values [(as @S_n S)], and values of datatypes
+      are constructor terms. Datatypes whose constructors are all constants
+      are complete; for other datatypes, acyclicity and exhaustiveness are
+      not enforced, and [check-sat] prints [unknown] when the model
+      found has a value that is not a finite constructor term.
+      Processing stops at the first error, which is printed as
+      [(error "...")]. The OCaml runtime lock is released while running. *)
+end
+
+(** Recording of calls into the library, to reproduce problems outside
+    of the application.
+
+    Each call is written as one line [name arg… -> result], where
+    contexts, solvers and jobs are named [c1], [s2], [j3], …, and terms
+    and literals are their integer. Tracing also starts when the
+    environment variable [BATSMT_TRACE] is set to a file name at the
+    time the first context is created. *)
+module Trace : sig
+  val start : string -> unit
+  (** [start file] records every following call into [file], replacing
+      its content. Objects created before this call cannot be replayed.
+      If a write fails, the error is printed once on stderr and tracing
+      stops.
+      @raise Failure if the file cannot be opened. *)
+
+  val stop : unit -> unit
+  (** Stop recording and close the file. *)
+
+  val replay : string -> int
+  (** [replay file] runs the trace in [file] again, on fresh contexts
+      and solvers, and returns how many results were checked: the result
+      of each [solve] and the output of each SMT-LIB script must be the
+      recorded one.
+      @raise Failure on the first mismatch, crash, or invalid call. *)
+
+  val minimize : string -> string option
+  (** [minimize file] shrinks the failing trace in [file] to a small
+      trace that still fails at the same call, in the same way.
+      Returns [None] if the trace does not fail. *)
 end
 
 val set_log_lvl : string -> unit
+
+val peak_mem : unit -> int option
+(** Peak resident memory of the whole process, in bytes, or [None]
+    if the system does not report it. *)
@@ -0,0 +1,54 @@
+//! Replay a trace recorded with `BATSMT_TRACE`.
+//!
+//! Checks that every `solve` gives the recorded result. With `--minimize`,
+//! a failing trace is shrunk and printed on stdout.
+
+use {
+    std::{env, fs, process},
+    batsmt_stubs::replay,
+};
+
+const USAGE: &'static str = "usage: batsmt-replay [--minimize] <trace>";
+
+fn main() {
+    let mut minimize = false;
+    let mut file = None;
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--minimize" => minimize = true,
+            "-h" | "--help" => { println!("{}", USAGE); return },
+            _ if file.is_none() && ! arg.starts_with('-') => file = Some(arg),
+            _ => {
+                eprintln!("{}", USAGE);
+                process::exit(2)
+            },
+        }
+    }
+    let file = match file {
+        Some(f) => f,
+        None => { eprintln!("{}", USAGE); process::exit(2) },
+    };
+
+    let calls =
+        fs::read_to_string(&file).map_err(|e| format!("cannot read {}: {}", file, e))
+        .and_then(|src| replay::parse(&src));
+    let calls = match calls {
+        Ok(c) => c,
+        Err(e) => { eprintln!("error: {}", e); process::exit(2) },
+    };
+
+    let out = replay::replay(&calls);
+    eprintln!("{}", out);
+    if ! out.is_failure() {
+        process::exit(if let replay::Outcome::Ok{..} = out { 0 } else { 2 })
+    }
+    if minimize {
+        // silences the panics of the replays, and restores the hook
+        let small = replay::minimize(&calls).unwrap();
+        eprintln!("minimized from {} to {} calls", calls.len(), small.len());
+        let mut s = String::new();
+        replay::write(&small, &mut s);
+        print!("{}", s);
+    }
+    process::exit(1)
+}
@@ -0,0 +1,81 @@
+//! Standalone solver.
+//!
+//! Reads a SMT-LIB 2 script or a DIMACS CNF problem from a file or from
+//! stdin, and prints the solver's answers.
+
+use {
+    std::{env, fs, io::{self, Read}, process},
+    batsmt_stubs::{Ctx, Solver, Lbool, smtlib, dimacs},
+};
+
+#[derive(Copy,Clone,PartialEq)]
+enum Format { Smt2, Dimacs }
+
+const USAGE: &'static str = "usage: batsmt [--smt2|--dimacs] [file]";
+
+fn run_dimacs(src: &str) -> Result<(), String> {
+    let mut ctx = Ctx::new();
+    let mut s = Solver::new(&mut ctx);
+    let vars = dimacs::load(&mut s, src)?;
+    if s.api_solve(&mut ctx) {
+        println!("s SATISFIABLE");
+        let mut line = String::from("v");
+        for (i, &lit) in vars.iter().enumerate() {
+            let v = (i+1) as i32;
+            let v = if s.api_value(lit) == Lbool::FALSE { -v } else { v };
+            line.push_str(&format!(" {}", v));
+            if line.len() > 70 {
+                println!("{}", line);
+                line = String::from("v");
+            }
+        }
+        println!("{} 0", line);
+    } else {
+        println!("s UNSATISFIABLE");
+    }
+    Ok(())
+}
+
+fn run_smt2(src: &str) -> Result<(), String> {
+    let mut ctx = Ctx::new();
+    let out = smtlib::parse_and_run(&mut ctx, src);
+    print!("{}", out);
+    Ok(())
+}
+
+fn main() {
+    let mut format = None;
+    let mut file = None;
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--smt2" => format = Some(Format::Smt2),
+            "--dimacs" => format = Some(Format::Dimacs),
+            "-h" | "--help" => { println!("{}", USAGE); return },
+            _ if file.is_none() && ! arg.starts_with('-') => file = Some(arg),
+            _ => {
+                eprintln!("{}", USAGE);
+                process::exit(2)
+            },
+        }
+    }
+
+    let format = format.unwrap_or_else(|| match &file {
+        Some(f) if f.ends_with(".cnf") || f.ends_with(".dimacs") => Format::Dimacs,
+        _ => Format::Smt2,
+    });
+    let src = match &file {
+        Some(f) => fs::read_to_string(f).map_err(|e| format!("cannot read {}: {}", f, e)),
+        None => {
+            let mut s = String::new();
+            io::stdin().read_to_string(&mut s).map(|_| s).map_err(|e| e.to_string())
+        },
+    };
+    let r = src.and_then(|src| match format {
+        Format::Smt2 => run_smt2(&src),
+        Format::Dimacs => run_dimacs(&src),
+    });
+    if let Err(e) = r {
+        eprintln!("error: {}", e);
+        process::exit(1)
+    }
+}
@@ -0,0 +1,45 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+const IDLE: u8 = 0;
+const BUSY: u8 = 1;
+const ORPHAN: u8 = 2;
+
+/// Flag marking a value as being used by a search that runs without
+/// the OCaml runtime lock.
+///
+/// A busy value can also be orphaned, if OCaml collects it while the
+/// search is still running; in that case whoever ends the search is
+/// responsible for freeing it.
+pub struct Busy(AtomicU8);
+
+impl Busy {
+    pub fn new() -> Self { Busy(AtomicU8::new(IDLE)) }
+
+    /// Is the value currently in use?
+    #[inline]
+    pub fn is_busy(&self) -> bool { self.0.load(Ordering::Acquire) != IDLE }
+
+    /// Mark as busy. Returns `false` if it already was.
+    #[inline]
+    pub fn acquire(&self) -> bool {
+        self.0.compare_exchange(IDLE, BUSY, Ordering::AcqRel, Ordering::Acquire).is_ok()
+    }
+
+    /// Mark as not busy anymore.
+    ///
+    /// Returns `false` if the value was orphaned meanwhile, in which case
+    /// the caller now owns it and must free it.
+    #[inline]
+    pub fn release(&self) -> bool {
+        self.0.compare_exchange(BUSY, IDLE, Ordering::AcqRel, Ordering::Acquire).is_ok()
+    }
+
+    /// Give up ownership of a busy value to the search using it.
+    ///
+    /// Returns `false` if the value is not busy, in which case the caller
+    /// still owns it.
+    #[inline]
+    pub fn orphan(&self) -> bool {
+        self.0.compare_exchange(BUSY, ORPHAN, Ordering::AcqRel, Ordering::Acquire).is_ok()
+    }
+}
@@ -0,0 +1,410 @@
+//! C API.
+//!
+//! Contexts and solvers are opaque pointers. Every function returns an
+//! error code (`BATSMT_OK` on success) and writes its result through its
+//! last argument. After an error, `batsmt_last_error` returns a message.
+//!
+//! The header `include/batsmt.h` is generated from this file by `make header`.
+
+#![allow(non_camel_case_types)]
+
+use {
+    std::{
+        cell::RefCell, ptr, slice,
+        ffi::{CStr, CString},
+        os::raw::{c_char, c_int},
+        panic::{self, AssertUnwindSafe},
+    },
+    batsmt_core::ast_u32::{self, AST},
+    crate::{
+        ctx::Ctx,
+        error::Error,
+        solver::{Solver, Lit, Lbool, Options},
+    },
+};
+
+/// A term of some context.
+pub type batsmt_term = u32;
+
+/// A literal of some solver. The negation of `l` is `-l`.
+pub type batsmt_lit = i32;
+
+/// Success.
+pub const BATSMT_OK: c_int = 0;
+/// A pointer argument is null.
+pub const BATSMT_ERR_NULL: c_int = 1;
+/// The context or solver is used by a search running in another thread.
+pub const BATSMT_ERR_BUSY: c_int = 2;
+/// An argument is invalid (not UTF-8, the buffer is too small, no such
+/// term or literal).
+pub const BATSMT_ERR_INVALID: c_int = 3;
+/// The call failed (ill-typed term, wrong arity, …).
+pub const BATSMT_ERR_FAILED: c_int = 4;
+/// The context cannot be freed, some of its solvers are still alive.
+pub const BATSMT_ERR_HAS_SOLVERS: c_int = 5;
+
+/// Values of literals.
+pub const BATSMT_TRUE: c_int = 0;
+pub const BATSMT_FALSE: c_int = 1;
+pub const BATSMT_UNDEF: c_int = 2;
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+type Result<T> = std::result::Result<T, c_int>;
+
+fn set_error(msg: &str) {
+    let msg = CString::new(msg.replace('\0', "")).unwrap();
+    LAST_ERROR.with(|e| *e.borrow_mut() = msg);
+}
+
+fn err<T>(code: c_int, msg: &str) -> Result<T> {
+    set_error(msg);
+    Err(code)
+}
+
+fn failed<T>(e: Error) -> Result<T> { err(BATSMT_ERR_FAILED, &e.to_string()) }
+
+fn invalid<T>(e: Error) -> Result<T> { err(BATSMT_ERR_INVALID, &e.to_string()) }
+
+// run `f`, turning panics into `BATSMT_ERR_FAILED`
+fn guard<F: FnOnce() -> Result<()>>(f: F) -> c_int {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => BATSMT_OK,
+        Ok(Err(code)) => code,
+        Err(e) => {
+            let msg =
+                e.downcast_ref::<&str>().map(|s| s.to_string())
+                .or_else(|| e.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown error".to_string());
+            set_error(&msg);
+            BATSMT_ERR_FAILED
+        },
+    }
+}
+
+unsafe fn ctx<'a>(p: *mut Ctx) -> Result<&'a mut Ctx> {
+    if p.is_null() { return err(BATSMT_ERR_NULL, "null context") }
+    if Ctx::busy_of(p).is_busy() { return err(BATSMT_ERR_BUSY, "context is in use") }
+    Ok(&mut *p)
+}
+
+unsafe fn solver<'a>(p: *mut Solver) -> Result<&'a mut Solver> {
+    if p.is_null() { return err(BATSMT_ERR_NULL, "null solver") }
+    if Solver::busy_of(p).is_busy() { return err(BATSMT_ERR_BUSY, "solver is in use") }
+    Ok(&mut *p)
+}
+
+unsafe fn out<T>(p: *mut T, x: T) -> Result<()> {
+    if p.is_null() { return err(BATSMT_ERR_NULL, "null output pointer") }
+    *p = x;
+    Ok(())
+}
+
+unsafe fn str<'a>(s: *const c_char) -> Result<&'a str> {
+    if s.is_null() { return err(BATSMT_ERR_NULL, "null string") }
+    match CStr::from_ptr(s).to_str() {
+        Ok(s) => Ok(s),
+        Err(_) => err(BATSMT_ERR_INVALID, "string is not valid UTF-8"),
+    }
+}
+
+unsafe fn array<'a, T>(p: *const T, n: usize) -> Result<&'a [T]> {
+    if n == 0 { Ok(&[]) }
+    else if p.is_null() { err(BATSMT_ERR_NULL, "null array") }
+    else { Ok(slice::from_raw_parts(p, n)) }
+}
+
+// `t`, if it is a term of `c`
+fn term(c: &Ctx, t: batsmt_term) -> Result<AST> {
+    c.check_term(ast_u32::manager_util::ast_from_u32(t)).or_else(invalid)
+}
+
+fn terms(c: &Ctx, ts: &[batsmt_term]) -> Result<Vec<AST>> {
+    ts.iter().map(|&t| term(c, t)).collect()
+}
+
+// `l`, if it is a literal of `s`
+fn lit(s: &Solver, l: batsmt_lit) -> Result<Lit> { s.check_lit(l).or_else(invalid) }
+
+/// Message of the last error in the current thread.
+#[no_mangle]
+pub extern "C" fn batsmt_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ptr())
+}
+
+/// Create a new context. Returns null on failure.
+#[no_mangle]
+pub extern "C" fn batsmt_ctx_new() -> *mut Ctx {
+    match panic::catch_unwind(|| Box::into_raw(Box::new(Ctx::new()))) {
+        Ok(p) => p,
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Free a context. Its terms must not be used afterwards.
+/// Its solvers must be freed first, or `BATSMT_ERR_HAS_SOLVERS` is returned.
+#[no_mangle]
+pub unsafe extern "C" fn batsmt_ctx_free(c: *mut Ctx) -> c_int {
+    guard(|| {
+        let n = ctx(c)?.n_solvers();
+        if n > 0 {
+            return err(BATSMT_ERR_HAS_SOLVERS, &format!("context still has {} solvers", n))
+        }
+        drop(Box::from_raw(c));
+        Ok(())
+    })
+}
+
+/// The boolean type.
+#[no_mangle]
+pub unsafe extern "C" fn batsmt_ty_bool(c: *mut Ctx, res: *mut batsmt_term) -> c_int {
+    guard(|| out(res, ctx(c)?.api_ty_bool().idx()))
+}
+
+/// The uninterpreted type named `name`. Fails if `name` is a function
+/// symbol.
+#[no_mangle]
+pub unsafe extern "C" fn batsmt_ty_const(
+    c: *mut Ctx, name: *const c_char, res: *mut batsmt_term
+) -> c_int {
+    guard(|| {
+        let name = str(name)?;
+        out(res, ctx(c)?.api_ty_const(name).or_else(invalid)?.idx())
+    })
+}
+
+/// The term `true` or `false`.
+#[no_mangle]
+pub unsafe extern "C" fn batsmt_term_bool(c: *mut Ctx, b: bool, res: *mut batsmt_term) -> c_int {
+    guard(|| out(res, ctx(c)?.api_bool(b).idx()))
+}
+
+/// Negation of a boolean term.
+#[no_mangle]
+pub unsafe extern "C" fn batsmt_term_not(
+    c: *mut Ctx, t: batsmt_term, res: *mut batsmt_term
+) -> c_int {
+    guard(|| {
+        let ctx = ctx(c)?;
+        let t = term(ctx, t)?;
+        out(res, ctx.api_not(t).idx())
+    })
+}
+
+/// The symbol `name`, with arguments of types `args[0 .. n_args]`
+/// and return type `ret`. Fails if `name` is declared with another
+/// signature.
+#[no_mangle]
+pub unsafe extern "C" fn batsmt_term_const(
+    c: *mut Ctx, name: *const c_char,
+    args: *const batsmt_term, n_args: usize, ret: batsmt_term,
+    res: *mut batsmt_term
+) -> c_int {
+    guard(|| {
+        let name = str(name)?;
+        let ctx = ctx(c)?;
+        let args = terms(ctx, array(args, n_args)?)?;
+        let ret = term(ctx, ret)?;
+        out(res, ctx.api_const(name, &args, ret).or_else(invalid)?.idx())
+    })
+}
+
+/// Same as `batsmt_term_const`, for a datatype constructor.
+#[no_mangle]
+pub unsafe extern "C" fn batsmt_term_cstor(
+    c: *mut Ctx, name: *const c_char,
+    args: *const batsmt_term, n_args: usize, ret: batsmt_term,
+    res: *mut batsmt_term
+) -> c_int {
+    guard(|| {
+        let name = str(name)?;
+        let ctx = ctx(c)?;
+        let args = terms(ctx, array(args, n_args)?)?;
+        let ret = term(ctx, ret)?;
+        out(res, ctx.api_cstor(name, &args, ret).or_else(invalid)?.idx())
+    })
+}
+
+/// Declare that the symbol `t` is a datatype constructor.
+#[no_mangle]
+pub unsafe extern "C" fn batsmt_term_set_cstor(c: *mut Ctx, t: batsmt_term) -> c_int {
+    guard(|| {
+        let ctx = ctx(c)?;
+        let t = term(ctx, t)?;
+        ctx.api_set_is_cstor(t).or_else(invalid)
+    })
+}
+
+/// Equality of two terms of the same type.
+#[no_mangle]
+pub unsafe extern "C" fn batsmt_term_eq(
+    c: *mut Ctx, t1: batsmt_term, t2: batsmt_term, res: *mut batsmt_term
+) -> c_int {
+    guard(|| {
+        let ctx = ctx(c)?;
+        let (t1, t2) = (term(ctx, t1)?, term(ctx, t2)?);
+        let t = ctx.api_eq(t1, t2).or_else(failed)?;
+        out(res, t.idx())
+    })
+}
+
+/// Application of the symbol `f` to `args[0 .. n_args]`.
+#[no_mangle]
+pub unsafe extern "C" fn batsmt_term_app(
+    c: *mut Ctx, f: batsmt_term, args: *const batsmt_term, n_args: usize,
+    res: *mut batsmt_term
+) -> c_int {
+    guard(|| {
+        let ctx = ctx(c)?;
+        let args = terms(ctx, array(args, n_args)?)?;
+        let f = term(ctx, f)?;
+        let t = ctx.api_app(f, &args).or_else(failed)?;
+        out(res, t.idx())
+    })
+}
+
+/// Create a solver for terms of `c`.
+#[no_mangle]
+pub unsafe extern "C" fn batsmt_solver_new(c: *mut Ctx, res: *mut *mut Solver) -> c_int {
+    guard(|| {
+        let ctx = ctx(c)?;
+        if res.is_null() { return err(BATSMT_ERR_NULL, "null output pointer") }
+        out(res, Box::into_raw(Box::new(Solver::new(ctx))))
+    })
+}
+
+/// Free a solver.
+#[no_mangle]
+pub unsafe extern "C" fn batsmt_solver_free(s: *mut Solver) -> c_int {
+    guard(|| {
+        solver(s)?;
+        drop(Box::from_raw(s));
+        Ok(())
+    })
+}
+
+/// Set the options of the solver (see `Batsmt.Solver.options`).
+#[no_mangle]
+pub unsafe extern "C" fn batsmt_solver_set_options(
+    s: *mut Solver, seed: u64, auto_simplify: bool, keep_clauses: bool
+) -> c_int {
+    guard(|| {
+        solver(s)?.api_set_options(Options{seed, auto_simplify, keep_clauses});
+        Ok(())
+    })
+}
+
+/// A fresh literal.
+#[no_mangle]
+pub unsafe extern "C" fn batsmt_solver_new_lit(s: *mut Solver, res: *mut batsmt_lit) -> c_int {
+    guard(|| out(res, solver(s)?.api_make_lit().to_int()))
+}
+
+/// The literal of the boolean term `t`.
+#[no_mangle]
+pub unsafe extern "C" fn batsmt_solver_new_term_lit(
+    s: *mut Solver, c: *mut Ctx, t: batsmt_term, res: *mut batsmt_lit
+) -> c_int {
+    guard(|| {
+        let s = solver(s)?;
+        let ctx = ctx(c)?;
+        s.check_ctx(ctx).or_else(failed)?;
+        let t = term(ctx, t)?;
+        out(res, s.api_make_term_lit(ctx, t).to_int())
+    })
+}
+
+/// Add the clause `lits[0 .. n_lits]`. Fails with `BATSMT_ERR_INVALID`
+/// if a literal was not created by `s`.
+#[no_mangle]
+pub unsafe extern "C" fn batsmt_solver_add_clause(
+    s: *mut Solver, lits: *const batsmt_lit, n_lits: usize
+) -> c_int {
+    guard(|| {
+        let lits: Vec<Lit> = array(lits, n_lits)?.iter().map(|&l| Lit::unsafe_from_int(l)).collect();
+        solver(s)?.api_add_clause_from(&lits).or_else(invalid)
+    })
+}
+
+/// Add the clauses in `lits[0 .. n_lits]`, each followed by `0`.
+/// Fails with `BATSMT_ERR_INVALID`, adding nothing, if a literal was
+/// not created by `s`.
+#[no_mangle]
+pub unsafe extern "C" fn batsmt_solver_add_clauses(
+    s: *mut Solver, lits: *const batsmt_lit, n_lits: usize
+) -> c_int {
+    guard(|| {
+        let lits = array(lits, n_lits)?;
+        solver(s)?.api_add_clauses_zero_sep(lits).or_else(invalid)
+    })
+}
+
+/// Add an assumption for the next call to `batsmt_solver_solve`.
+#[no_mangle]
+pub unsafe extern "C" fn batsmt_solver_add_assumption(s: *mut Solver, l: batsmt_lit) -> c_int {
+    guard(|| {
+        let s = solver(s)?;
+        let l = lit(s, l)?;
+        s.api_add_assumption(l);
+        Ok(())
+    })
+}
+
+/// Check satisfiability under the current assumptions, which are then cleared.
+#[no_mangle]
+pub unsafe extern "C" fn batsmt_solver_solve(s: *mut Solver, c: *mut Ctx, res: *mut bool) -> c_int {
+    guard(|| {
+        let s = solver(s)?;
+        let ctx = ctx(c)?;
+        s.check_ctx(ctx).or_else(failed)?;
+        if res.is_null() { return err(BATSMT_ERR_NULL, "null output pointer") }
+        out(res, s.api_solve(ctx))
+    })
+}
+
+/// Value of `l` in the model: `BATSMT_TRUE`, `BATSMT_FALSE` or `BATSMT_UNDEF`.
+#[no_mangle]
+pub unsafe extern "C" fn batsmt_solver_value(s: *mut Solver, l: batsmt_lit, res: *mut c_int) -> c_int {
+    guard(|| {
+        let s = solver(s)?;
+        let v = s.api_value(lit(s, l)?);
+        let v =
+            if v == Lbool::TRUE { BATSMT_TRUE }
+            else if v == Lbool::FALSE { BATSMT_FALSE }
+            else { BATSMT_UNDEF };
+        out(res, v)
+    })
+}
+
+/// Copy the unsat core of the last call to `batsmt_solver_solve` into
+/// `buf[0 .. cap]`, and its size into `*n`. If `cap` is too small,
+/// only `*n` is set and `BATSMT_ERR_INVALID` is returned.
+#[no_mangle]
+pub unsafe extern "C" fn batsmt_solver_unsat_core(
+    s: *mut Solver, buf: *mut batsmt_lit, cap: usize, n: *mut usize
+) -> c_int {
+    guard(|| {
+        let core = solver(s)?.api_unsat_core();
+        out(n, core.len())?;
+        if core.len() > cap { return err(BATSMT_ERR_INVALID, "buffer is too small") }
+        if core.len() > 0 && buf.is_null() { return err(BATSMT_ERR_NULL, "null buffer") }
+        for (i, &l) in core.iter().enumerate() {
+            *buf.add(i) = Lit::new(l).to_int();
+        }
+        Ok(())
+    })
+}
+
+/// Is `l` in the unsat core of the last call to `batsmt_solver_solve`?
+#[no_mangle]
+pub unsafe extern "C" fn batsmt_solver_unsat_core_contains(
+    s: *mut Solver, l: batsmt_lit, res: *mut bool
+) -> c_int {
+    guard(|| {
+        let s = solver(s)?;
+        let l = lit(s, l)?;
+        out(res, s.api_unsat_core_contains(l))
+    })
+}
@@ -0,0 +1,167 @@
+//! Copies of term DAGs from one context into another.
+//!
+//! A DAG is first turned into a list of `Node`s, which do not depend on
+//! any context, then rebuilt in the target context.
+
+use {
+    fxhash::FxHashMap,
+    batsmt_core::{AstView, Manager, ast_u32::AST},
+    crate::ctx::{Ctx, SymKind},
+};
+
+/// A node of a term DAG, referring to earlier nodes by their index.
+#[derive(Clone,Debug)]
+pub enum Node {
+    Bool(bool),
+    TyBool,
+    Ty(String),
+    Const {
+        name: String,
+        args: Vec<usize>,
+        ret: usize,
+        cstor: bool,
+    },
+    Not(usize),
+    Eq(usize, usize),
+    App(usize, Vec<usize>),
+}
+
+/// Builds the nodes of terms of a context, sharing common subterms.
+pub struct Builder<'a> {
+    ctx: &'a Ctx,
+    nodes: Vec<Node>,
+    idx: FxHashMap<AST, usize>,
+}
+
+impl<'a> Builder<'a> {
+    pub fn new(ctx: &'a Ctx) -> Self {
+        Builder{ctx, nodes: vec![], idx: FxHashMap::default()}
+    }
+
+    /// The nodes built so far, in order.
+    pub fn into_nodes(self) -> Vec<Node> { self.nodes }
+
+    /// Index of the node for `t`, adding it and its subterms if needed.
+    pub fn node(&mut self, t: AST) -> usize {
+        // terms can be arbitrarily deep, so use an explicit stack: a term
+        // is pushed again after its children, and added once they are.
+        let mut stack = vec![(t, false)];
+        while let Some((u, children_done)) = stack.pop() {
+            if self.idx.contains_key(&u) { continue }
+            if children_done {
+                let n = self.mk_node(u);
+                self.idx.insert(u, self.nodes.len());
+                self.nodes.push(n);
+            } else {
+                stack.push((u, true));
+                for v in self.children(u) {
+                    if ! self.idx.contains_key(&v) { stack.push((v, false)) }
+                }
+            }
+        }
+        self.idx[&t]
+    }
+
+    // terms that the node of `t` refers to
+    fn children(&self, t: AST) -> Vec<AST> {
+        let ctx = self.ctx;
+        if t == ctx.b.true_ || t == ctx.b.false_ || t == ctx.b.bool_ { return vec![] }
+        match ctx.sym_kind(&t) {
+            Some(SymKind::Ty) => vec![],
+            Some(SymKind::Const{args, ret}) => {
+                args.iter().cloned().chain(Some(*ret)).collect()
+            },
+            None => match ctx.m.view(&t) {
+                AstView::App{f, args} if *f == ctx.b.not_ || *f == ctx.b.eq => args.to_vec(),
+                AstView::App{f, args} => Some(*f).into_iter().chain(args.iter().cloned()).collect(),
+                _ => vec![],
+            },
+        }
+    }
+
+    // node of `t`, once its children have one
+    fn mk_node(&self, t: AST) -> Node {
+        let ctx = self.ctx;
+        let i = |u: &AST| self.idx[u];
+        if t == ctx.b.true_ {
+            Node::Bool(true)
+        } else if t == ctx.b.false_ {
+            Node::Bool(false)
+        } else if t == ctx.b.bool_ {
+            Node::TyBool
+        } else {
+            match ctx.sym_kind(&t) {
+                Some(SymKind::Ty) => Node::Ty(ctx.api_const_get_name(t).unwrap().to_string()),
+                Some(SymKind::Const{args, ret}) => {
+                    let name = ctx.api_const_get_name(t).unwrap().to_string();
+                    Node::Const{name, args: args.iter().map(i).collect(), ret: i(ret),
+                        cstor: ctx.is_cstor(&t)}
+                },
+                None => match ctx.m.view(&t) {
+                    AstView::App{f, args} if *f == ctx.b.not_ => Node::Not(i(&args[0])),
+                    AstView::App{f, args} if *f == ctx.b.eq => Node::Eq(i(&args[0]), i(&args[1])),
+                    AstView::App{f, args} => Node::App(i(f), args.iter().map(i).collect()),
+                    _ => panic!("cannot copy term {}", batsmt_pretty::pp1(&ctx.m, &t)),
+                },
+            }
+        }
+    }
+}
+
+/// Rebuild `nodes` in `ctx`, and return the term of each node.
+pub fn build(nodes: &[Node], ctx: &mut Ctx) -> Vec<AST> {
+    let mut terms: Vec<AST> = Vec::with_capacity(nodes.len());
+    // the nodes come from a valid context, so rebuilding them cannot fail
+    for n in nodes.iter() {
+        let t = match n {
+            Node::Bool(b) => ctx.api_bool(*b),
+            Node::TyBool => ctx.api_ty_bool(),
+            Node::Ty(name) => ctx.api_ty_const(name).unwrap(),
+            Node::Const{name, args, ret, cstor} => {
+                let args: Vec<AST> = args.iter().map(|&i| terms[i]).collect();
+                if *cstor { ctx.api_cstor(name, &args, terms[*ret]).unwrap() }
+                else { ctx.api_const(name, &args, terms[*ret]).unwrap() }
+            },
+            Node::Not(u) => ctx.api_not(terms[*u]),
+            Node::Eq(a, b) => ctx.api_eq(terms[*a], terms[*b]).unwrap(),
+            Node::App(f, args) => {
+                let args: Vec<AST> = args.iter().map(|&a| terms[a]).collect();
+                ctx.api_app(terms[*f], &args).unwrap()
+            },
+        };
+        terms.push(t);
+    }
+    terms
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deep_term() {
+        // deep enough to overflow the stack with a recursive copy
+        let mut ctx = Ctx::new();
+        let ty = ctx.api_ty_const("u").unwrap();
+        let f = ctx.api_const("f", &[ty], ty).unwrap();
+        let c = ctx.api_const("c", &[], ty).unwrap();
+        let mut t = c;
+        for _ in 0 .. 200_000 { t = ctx.api_app(f, &[t]).unwrap() }
+
+        let mut b = Builder::new(&ctx);
+        let i = b.node(t);
+        let nodes = b.into_nodes();
+        assert_eq!(nodes.len(), 200_000 + 3); // u, f, c and the applications
+
+        let mut ctx2 = Ctx::new();
+        let ts = build(&nodes, &mut ctx2);
+        let mut u = ts[i];
+        let mut depth = 0;
+        while let Ok(args) = ctx2.api_app_get_args(u) {
+            u = args[0];
+            depth += 1;
+        }
+        assert_eq!(depth, 200_000);
+        assert_eq!(ctx2.api_const_get_name(u).unwrap(), "c");
+    }
+}
@@ -8,6 +8,8 @@ use {
     batsmt_cc::{self as cc, CCView},
     batsmt_solver as solver,
     batsmt_pretty as pp,
+    crate::{busy::Busy, copy, error::{Error, Result}},
+    std::{fmt::Write, sync::{Arc, atomic::{AtomicU64, Ordering}}},
 };
 
 /// The Manager we use.
@@ -36,22 +38,41 @@ pub enum SymKind {
 
 /// The main context.
 pub struct Ctx {
-    pub m: M,
+    // not public: terms must be created by `Ctx`, which counts them
+    pub(crate) m: M,
     pub lmb: LitMapBuiltins,
     pub b: Builtins,
     syms: FxHashMap<String, AST>, // caching of symbols
     kinds: FxHashMap<AST, SymKind>,
     flags: Flags,
-    f: Option<AST>, // for application
-    args: Vec<AST>, // for application
+    busy: Busy,
+    id: u64,
+    n_terms: u32, // 1 + largest term index so far
+    solvers: Arc<()>, // each solver created with this context holds a clone
 }
 
+// source of unique context IDs
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
 #[derive(Default,Clone)]
 struct Flags {
     cstor: BitSet,
     selector: BitSet,
 }
 
+/// Statistics of a context.
+#[derive(Copy,Clone,Debug,Default)]
+pub struct CtxStats {
+    /// Hash-consed terms, including types, symbols and builtins.
+    pub n_terms: usize,
+    /// Declared types and symbols.
+    pub n_syms: usize,
+    /// Datatype constructors.
+    pub n_cstors: usize,
+    /// Estimated heap usage, in bytes.
+    pub mem_size: usize,
+}
+
 /// An enum for the various kinds of terms we have.
 #[repr(u8)]
 #[derive(Eq,PartialEq,Copy,Clone)]
@@ -64,6 +85,18 @@ pub enum AstKind {
     Not,
 }
 
+/// Kinds of nodes for `Ctx::api_import`.
+pub mod node_kind {
+    pub const BOOL: i32 = 0;
+    pub const TY_BOOL: i32 = 1;
+    pub const TY: i32 = 2;
+    pub const CONST: i32 = 3;
+    pub const CSTOR: i32 = 4;
+    pub const NOT: i32 = 5;
+    pub const EQ: i32 = 6;
+    pub const APP: i32 = 7;
+}
+
 pub mod ctx {
     use {super::*, batsmt_core::Manager};
     use cc::intf::{
@@ -76,12 +109,129 @@ pub mod ctx {
             let mut m = HManager::new();
             let b = Builtins::new(&mut m);
             let lmb = b.clone().into();
+            let n_terms = b.select.idx() + 1; // the last builtin
             Ctx {
-                m, b, lmb, f: None, args: vec!(), kinds: FxHashMap::default(),
+                m, b, lmb, kinds: FxHashMap::default(),
                 flags: Default::default(), syms: FxHashMap::default(),
+                busy: Busy::new(), id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+                n_terms, solvers: Arc::new(()),
+            }
+        }
+
+        // record the creation of `t`
+        #[inline]
+        fn seen(&mut self, t: AST) -> AST {
+            if t.idx() >= self.n_terms { self.n_terms = t.idx() + 1 }
+            t
+        }
+
+        /// Upper bound on the number of terms (types and symbols included).
+        #[inline]
+        pub fn n_terms(&self) -> usize { self.n_terms as usize }
+
+        /// Check that `t` is a term of this context.
+        pub fn check_term(&self, t: AST) -> Result<AST> {
+            if t.idx() < self.n_terms { Ok(t) } else { Err(Error::UnknownTerm(t.idx())) }
+        }
+
+        /// Number of live solvers created with this context.
+        pub fn n_solvers(&self) -> usize { Arc::strong_count(&self.solvers) - 1 }
+
+        // token held by a solver created with this context, see `n_solvers`
+        pub(crate) fn solver_token(&self) -> Arc<()> { self.solvers.clone() }
+
+        /// Rough estimate of the memory used by this context, in bytes.
+        pub fn mem_size(&self) -> usize {
+            // hashconsing table, node, type, and flags of each term
+            const TERM_SIZE: usize = 64;
+            std::mem::size_of::<Self>()
+                + self.n_terms() * TERM_SIZE
+                + self.syms.len() * (std::mem::size_of::<(String, AST)>() + 16)
+        }
+
+        /// Copy the DAGs of `roots` into a fresh context, and return it with
+        /// the copy of each root.
+        ///
+        /// This is how terms are collected: terms can't be removed from
+        /// the hashconsing table, and solvers refer to terms by index, so
+        /// instead the live terms are copied and the old context is dropped
+        /// along with its solvers. Symbols are copied only if reachable.
+        ///
+        /// Only `roots` are kept: there are no weak handles, and the terms
+        /// of the solvers of `self` are not roots, since these solvers
+        /// cannot be moved to the new context anyway.
+        pub fn api_compact(&self, roots: &[AST]) -> (Ctx, Vec<AST>) {
+            let mut b = copy::Builder::new(self);
+            let idx: Vec<usize> = roots.iter().map(|&t| b.node(t)).collect();
+            let mut ctx = Ctx::new();
+            let terms = copy::build(&b.into_nodes(), &mut ctx);
+            let roots = idx.iter().map(|&i| terms[i]).collect();
+            (ctx, roots)
+        }
+
+        /// Current size of the context.
+        pub fn api_stats(&self) -> CtxStats {
+            CtxStats{
+                n_terms: self.n_terms(),
+                n_syms: self.syms.len(),
+                n_cstors: self.flags.cstor.len(),
+                mem_size: self.mem_size(),
+            }
+        }
+
+        /// Print the statistics, the symbols, then every term, for debugging.
+        ///
+        /// Each term is printed as its head followed by the indices of its
+        /// arguments, such as `#12: f #7 #9`.
+        pub fn api_dump(&self, out: &mut String) {
+            let st = self.api_stats();
+            let _ = writeln!(out, "; ctx {}: {} terms, {} symbols, {} constructors, ~{} bytes",
+                self.id, st.n_terms, st.n_syms, st.n_cstors, st.mem_size);
+            for (t, k) in self.symbols() {
+                let _ = match k {
+                    SymKind::Ty => writeln!(out, "; type {}", pp::pp1(&self.m, &t)),
+                    SymKind::Const{args, ret} => {
+                        let _ = write!(out, "; {} {} :",
+                            if self.is_cstor(&t) { "cstor" } else { "const" }, pp::pp1(&self.m, &t));
+                        for ty in args.iter() { let _ = write!(out, " {} ->", pp::pp1(&self.m, ty)); }
+                        writeln!(out, " {}", pp::pp1(&self.m, ret))
+                    },
+                };
+            }
+            // one level per term, so that shared subterms are printed once
+            for i in 0 .. self.n_terms {
+                let t = batsmt_core::ast_u32::manager_util::ast_from_u32(i);
+                let _ = write!(out, "#{}:", i);
+                let _ = match self.m.view(&t) {
+                    AstView::Const(s) => writeln!(out, " {}", s),
+                    AstView::Index(n) => writeln!(out, " index {}", n),
+                    AstView::App{f, args} => {
+                        let _ = match self.m.view(f) {
+                            AstView::Const(s) => write!(out, " {}", s),
+                            _ => write!(out, " #{}", f.idx()),
+                        };
+                        for a in args.iter() { let _ = write!(out, " #{}", a.idx()); }
+                        writeln!(out)
+                    },
+                };
             }
         }
 
+        /// Unique identifier of this context.
+        #[inline]
+        pub fn id(&self) -> u64 { self.id }
+
+        /// Flag set while a search runs on this context.
+        #[inline]
+        pub fn busy(&self) -> &Busy { &self.busy }
+
+        /// Flag of the context at `p`, obtained without forming a reference
+        /// to the context itself (a search might hold a `&mut` to it).
+        #[inline]
+        pub unsafe fn busy_of<'a>(p: *const Ctx) -> &'a Busy {
+            &*std::ptr::addr_of!((*p).busy)
+        }
+
         /// Copy of builtins
         pub fn builtins<U>(&self) -> U
             where Builtins: Into<U>
@@ -89,6 +239,16 @@ pub mod ctx {
 
         pub fn lmb(&self) -> LitMapBuiltins { self.lmb.clone() }
 
+        /// Kind of the symbol `t`, if it was declared by `api_ty_const` or `api_const`.
+        pub fn sym_kind(&self, t: &AST) -> Option<&SymKind> { self.kinds.get(t) }
+
+        /// All declared symbols, in creation order.
+        pub fn symbols(&self) -> Vec<(AST, &SymKind)> {
+            let mut v: Vec<_> = self.kinds.iter().map(|(t,k)| (*t,k)).collect();
+            v.sort_by_key(|(t,_)| t.idx());
+            v
+        }
+
         pub fn is_cstor(&self, t: &AST) -> bool { self.flags.cstor.contains(t.idx() as usize) }
 
         pub fn set_cstor(&mut self, t: &AST) { self.flags.cstor.insert(t.idx() as usize); }
@@ -103,40 +263,68 @@ pub mod ctx {
 
         pub fn api_ty_bool(&self) -> AST { self.b.bool_ }
 
-        pub fn api_ty_const(&mut self, s: &str) -> AST {
+        /// Declare the type `s`, or return it if it already exists.
+        ///
+        /// Fails if `s` is already declared as a function symbol.
+        pub fn api_ty_const(&mut self, s: &str) -> Result<AST> {
             match self.syms.get(s) {
-                Some(t) => *t,
+                Some(t) => match self.kinds.get(t) {
+                    Some(SymKind::Ty) => Ok(*t),
+                    _ => Err(Error::Redeclared(s.to_string())),
+                },
                 None => {
                     let t = self.m.mk_const(s, None);
+                    let t = self.seen(t);
                     self.syms.insert(s.to_string(), t);
                     self.kinds.insert(t, SymKind::Ty);
-                    t
+                    Ok(t)
                 }
             }
         }
 
-        pub fn api_const(&mut self, s: &str, ty_args: &[AST], ty_ret: AST) -> AST {
+        /// Declare the function symbol `s`, or return it if it already exists.
+        ///
+        /// Fails if `s` is already declared with another signature, or as a type.
+        pub fn api_const(&mut self, s: &str, ty_args: &[AST], ty_ret: AST) -> Result<AST> {
             match self.syms.get(s) {
-                Some(t) => *t,
+                Some(t) => match self.kinds.get(t) {
+                    Some(SymKind::Const{args, ret}) if &args[..] == ty_args && *ret == ty_ret => Ok(*t),
+                    _ => Err(Error::Redeclared(s.to_string())),
+                },
                 None => {
                     let t = {
                         let ty = if ty_args.len() == 0 { Some(ty_ret) } else { None };
-                        self.m.mk_const(s, ty)
+                        let t = self.m.mk_const(s, ty);
+                        self.seen(t)
                     };
                     let sym_kind =
                         SymKind::Const {
                             args: ty_args.iter().cloned().collect(), ret: ty_ret};
                     self.syms.insert(s.to_string(), t);
                     self.kinds.insert(t, sym_kind);
-                    t
+                    Ok(t)
                 }
             }
         }
 
+        /// Declare the constructor `s`, or return it if it already exists.
+        ///
+        /// Fails like `api_const`, and if `s` is a plain constant.
+        pub fn api_cstor(&mut self, s: &str, ty_args: &[AST], ty_ret: AST) -> Result<AST> {
+            let fresh = ! self.syms.contains_key(s);
+            let c = self.api_const(s, ty_args, ty_ret)?;
+            if fresh { self.set_cstor(&c) }
+            else if ! self.is_cstor(&c) { return Err(Error::Redeclared(s.to_string())) }
+            Ok(c)
+        }
+
         pub fn api_not(&mut self, t: AST) -> AST {
             if t == self.b.true_ { self.b.false_ }
             else if t == self.b.false_ { self.b.true_ }
-            else { self.m.mk_app(self.b.not_, &[t], Some(self.b.bool_)) }
+            else {
+                let u = self.m.mk_app(self.b.not_, &[t], Some(self.b.bool_));
+                self.seen(u)
+            }
         }
 
         pub fn api_kind(&self, t: AST) -> AstKind {
@@ -156,95 +344,171 @@ pub mod ctx {
             }
         }
 
-        pub fn api_get_bool(&self, t: AST) -> bool {
-            if t == self.b.true_ { true }
-            else if t == self.b.false_ { false }
-            else { panic!("term is not a boolean") }
+        pub fn api_get_bool(&self, t: AST) -> Result<bool> {
+            if t == self.b.true_ { Ok(true) }
+            else if t == self.b.false_ { Ok(false) }
+            else { Err(Error::NotABool(t)) }
         }
 
-        pub fn api_const_get_name(&self, t: AST) -> &str {
+        pub fn api_const_get_name(&self, t: AST) -> Result<&str> {
             match self.m.view(&t) {
-                AstView::Const(s) => s,
-                _ => panic!("term is not a constant")
+                AstView::Const(s) => Ok(s),
+                _ => Err(Error::NotAConst(t)),
             }
         }
 
-        pub fn api_app_get_fun(&self, t: AST) -> AST {
+        pub fn api_app_get_fun(&self, t: AST) -> Result<AST> {
             match self.m.view(&t) {
-                AstView::App{f, ..} => *f,
-                _ => panic!("term is not an app")
+                AstView::App{f, ..} => Ok(*f),
+                _ => Err(Error::NotAnApp(t)),
             }
         }
 
-        pub fn api_app_get_args(&self, t: AST) -> &[AST] {
+        pub fn api_app_get_args(&self, t: AST) -> Result<&[AST]> {
             match self.m.view(&t) {
-                AstView::App{args, ..} => args,
-                _ => panic!("term is not an app")
+                AstView::App{args, ..} => Ok(args),
+                _ => Err(Error::NotAnApp(t)),
             }
         }
 
-        pub fn api_bool(&mut self, b: bool) -> AST {
-            if b { self.b.true_ } else { self.b.false_ }
-        }
-
-        pub fn api_app_fun(&mut self, f: AST) {
-            self.f = Some(f);
-            self.args.clear();
+        /// Subterms of `t`, including `t`, each after its arguments.
+        ///
+        /// Only arguments of applications are followed, not function symbols.
+        pub fn api_subterms(&self, t: AST) -> Vec<AST> {
+            let mut res = vec!();
+            let mut seen = BitSet::new();
+            let mut stack = vec!((t, false)); // (term, are its args done?)
+            while let Some((u, done)) = stack.pop() {
+                if done {
+                    res.push(u);
+                } else if seen.insert(u.idx() as usize) {
+                    stack.push((u, true));
+                    if let AstView::App{args, ..} = self.m.view(&u) {
+                        for &a in args.iter().rev() {
+                            if ! seen.contains(a.idx() as usize) { stack.push((a, false)) }
+                        }
+                    }
+                }
+            }
+            res
         }
 
-        pub fn api_app_arg(&mut self, t: AST) {
-            debug_assert!(self.f.is_some());
-            self.args.push(t)
+        pub fn api_bool(&mut self, b: bool) -> AST {
+            if b { self.b.true_ } else { self.b.false_ }
         }
 
-        pub fn api_app_finalize(&mut self) -> AST {
-            let f = self.f.unwrap();
+        /// Application of the symbol `f` to `args`.
+        pub fn api_app(&mut self, f: AST, args: &[AST]) -> Result<AST> {
             let ty = match &self.kinds.get(&f) {
-                Some(SymKind::Const{args, ret}) => {
-                    if args.len() != self.args.len() {
-                        panic!("wrong arity for {} (expect {} args, got {})",
-                        pp::pp1(&self.m, &f), args.len(), self.args.len())
+                Some(SymKind::Const{args: ty_args, ret}) => {
+                    if ty_args.len() != args.len() {
+                        return Err(Error::WrongArity{
+                            f: pp::pp1(&self.m, &f).to_string(),
+                            expected: ty_args.len(), got: args.len(),
+                        })
                     };
+                    for (ty, a) in ty_args.iter().zip(args.iter()) {
+                        if self.m.ty(a) != Some(*ty) {
+                            return Err(Error::IllTyped(format!(
+                                "{}: argument {} does not have type {}",
+                                pp::pp1(&self.m, &f), pp::pp1(&self.m, a), pp::pp1(&self.m, ty))))
+                        }
+                    }
                     *ret
                 },
-                _ => panic!("cannot apply {:?}", f),
+                _ => return Err(Error::NotAFunction(f)),
             };
-            let t = self.m.mk_app(f, &self.args, Some(ty));
-            self.f = None;
-            self.args.clear();
-            t
+            let t = self.m.mk_app(f, args, Some(ty));
+            Ok(self.seen(t))
         }
 
-        pub fn api_eq(&mut self, mut t1: AST, mut t2: AST) -> AST {
+        pub fn api_eq(&mut self, mut t1: AST, mut t2: AST) -> Result<AST> {
             // check types
             match (self.m.ty(&t1), self.m.ty(&t2)) {
                 (Some(ty1), Some(ty2)) => {
                     if ty1 != ty2 {
-                        panic!("mk_eq: {} and {} have incompatible types",
-                               pp::pp1(&self.m, &t1), pp::pp1(&self.m, &t2));
+                        return Err(Error::IllTyped(format!(
+                            "mk_eq: {} and {} have incompatible types",
+                            pp::pp1(&self.m, &t1), pp::pp1(&self.m, &t2))))
                     }
                 },
-                _ => panic!("mk_eq: terms should be typed"),
+                _ => return Err(Error::IllTyped("mk_eq: terms should be typed".to_string())),
             };
             if t1.idx()>t2.idx() {
                 std::mem::swap(&mut t1, &mut t2); // normalize
             }
-            self.m.mk_app(self.b.eq, &[t1, t2], Some(self.b.bool_))
+            let t = self.m.mk_app(self.b.eq, &[t1, t2], Some(self.b.bool_));
+            Ok(self.seen(t))
         }
 
-        pub fn api_set_is_cstor(&mut self, t: AST) {
-            debug_assert!({let k = self.api_kind(t); k == AstKind::Const|| k == AstKind::Cstor});
-            self.set_cstor(&t)
+        /// Flag the symbol `t` as a constructor.
+        pub fn api_set_is_cstor(&mut self, t: AST) -> Result<()> {
+            match self.kinds.get(&t) {
+                Some(SymKind::Const{..}) => { self.set_cstor(&t); Ok(()) },
+                _ => Err(Error::NotAConst(t)),
+            }
         }
 
-        pub fn api_select(&mut self, c: AST, _i: u32, _sub: AST) -> AST {
+        pub fn api_select(&mut self, c: AST, _i: u32, _sub: AST) -> Result<AST> {
             debug_assert!(self.api_kind(c) == AstKind::Cstor);
-            panic!("cannot build `select`");
+            Err(Error::Unsupported("select terms"))
             /*
             let args = [c, self.m.mk_idx(i), sub];
             self.m.mk_app(self.b.select, &args)
             */
         }
+
+        /// Build the DAG encoded in `nodes`, and return the term of each node.
+        ///
+        /// Each node is `kind sym n c_1 … c_n` where `kind` is one of
+        /// `node_kind`, `sym` is an index in `names` (or the value of a boolean),
+        /// and the children `c_i` are indices of earlier nodes. The children are:
+        /// - `BOOL`, `TY_BOOL`, `TY`: none
+        /// - `CONST`, `CSTOR`: the types of the arguments, then the return type
+        /// - `NOT`: the negated term; `EQ`: both sides
+        /// - `APP`: the function, then the arguments
+        pub fn api_import(&mut self, names: &[&str], nodes: &[i32]) -> Result<Vec<AST>> {
+            fn bad<T>(node: usize, msg: &'static str) -> Result<T> {
+                Err(Error::InvalidNode{node, msg})
+            }
+            let mut terms: Vec<AST> = vec!();
+            let mut args: Vec<AST> = vec!();
+            let mut i = 0;
+            while i < nodes.len() {
+                let node = terms.len();
+                if i + 3 > nodes.len() { return bad(node, "truncated node") }
+                let (kind, sym, n) = (nodes[i], nodes[i+1], nodes[i+2]);
+                if n < 0 || i + 3 + n as usize > nodes.len() { return bad(node, "truncated node") }
+                args.clear();
+                for &c in nodes[i+3 .. i+3+n as usize].iter() {
+                    if c < 0 || c as usize >= node { return bad(node, "child is not an earlier node") }
+                    args.push(terms[c as usize]);
+                }
+                i += 3 + n as usize;
+                let name = |sym: i32| match names.get(sym as usize) {
+                    Some(s) if sym >= 0 => Ok(*s),
+                    _ => bad(node, "no such name"),
+                };
+                use super::node_kind::*;
+                let t = match kind {
+                    BOOL if n == 0 => self.api_bool(sym != 0),
+                    TY_BOOL if n == 0 => self.api_ty_bool(),
+                    TY if n == 0 => self.api_ty_const(name(sym)?)?,
+                    CONST | CSTOR if n >= 1 => {
+                        let (ret, ty_args) = args.split_last().unwrap();
+                        if kind == CSTOR { self.api_cstor(name(sym)?, ty_args, *ret)? }
+                        else { self.api_const(name(sym)?, ty_args, *ret)? }
+                    },
+                    NOT if n == 1 => self.api_not(args[0]),
+                    EQ if n == 2 => self.api_eq(args[0], args[1])?,
+                    APP if n >= 1 => self.api_app(args[0], &args[1..])?,
+                    BOOL ..= APP => return bad(node, "wrong number of children"),
+                    _ => return bad(node, "unknown kind"),
+                };
+                terms.push(t);
+            }
+            Ok(terms)
+        }
     }
 
     impl theory::BoolLitCtx for Ctx {
@@ -371,3 +635,130 @@ mod builtins {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {super::*, super::ctx::Ctx, super::node_kind::*};
+
+    #[test]
+    fn import() {
+        let mut ctx = Ctx::new();
+        // (= (f a) b), (not (f a)) with f: u -> Bool
+        let names = ["u", "f", "a", "b"];
+        let nodes = [
+            TY, 0, 0,
+            TY_BOOL, 0, 0,
+            CONST, 1, 2, 0, 1,
+            CONST, 2, 1, 0,
+            CONST, 3, 1, 1,
+            APP, 0, 2, 2, 3,
+            EQ, 0, 2, 5, 4,
+            NOT, 0, 1, 5,
+            BOOL, 1, 0,
+        ];
+        let ts = ctx.api_import(&names, &nodes).unwrap();
+        assert_eq!(ts.len(), 9);
+        let u = ctx.api_ty_const("u").unwrap();
+        let bool_ = ctx.api_ty_bool();
+        let f = ctx.api_const("f", &[u], bool_).unwrap();
+        let a = ctx.api_const("a", &[], u).unwrap();
+        let b = ctx.api_const("b", &[], bool_).unwrap();
+        let fa = ctx.api_app(f, &[a]).unwrap();
+        assert_eq!(&ts[..5], &[u, bool_, f, a, b]);
+        assert_eq!(ts[5], fa);
+        assert_eq!(ts[6], ctx.api_eq(fa, b).unwrap());
+        assert_eq!(ts[7], ctx.api_not(fa));
+        assert_eq!(ts[8], ctx.api_bool(true));
+        // importing again gives the same terms
+        assert_eq!(ctx.api_import(&names, &nodes).unwrap(), ts);
+    }
+
+    #[test]
+    fn import_errors() {
+        let mut ctx = Ctx::new();
+        let names = ["u", "a", "c"];
+        let bad = |node, msg| Err(Error::InvalidNode{node, msg});
+        let mut imp = |nodes: &[i32]| ctx.api_import(&names, nodes);
+        assert_eq!(imp(&[TY, 0]), bad(0, "truncated node"));
+        assert_eq!(imp(&[TY, 0, 0, NOT, 0, 2, 0]), bad(1, "truncated node"));
+        assert_eq!(imp(&[TY, 0, 0, CONST, 1, 1, 1]), bad(1, "child is not an earlier node"));
+        assert_eq!(imp(&[TY, 0, 0, CONST, 1, 1, -1]), bad(1, "child is not an earlier node"));
+        assert_eq!(imp(&[TY, 7, 0]), bad(0, "no such name"));
+        assert_eq!(imp(&[TY, 0, 1, 0]), bad(0, "wrong number of children"));
+        assert_eq!(imp(&[8, 0, 0]), bad(0, "unknown kind"));
+        // `a: u`, then `a: Bool`
+        assert_eq!(imp(&[TY, 0, 0, CONST, 1, 1, 0, TY_BOOL, 0, 0, CONST, 1, 1, 2]),
+            Err(Error::Redeclared("a".to_string())));
+        // `a` is not a constructor
+        assert_eq!(imp(&[TY, 0, 0, CSTOR, 1, 1, 0]), Err(Error::Redeclared("a".to_string())));
+        // `c: u` applied to itself, and `(= a true)`
+        assert_eq!(imp(&[TY, 0, 0, CONST, 2, 1, 0, APP, 0, 2, 1, 1]).is_err(), true);
+        assert_eq!(imp(&[TY, 0, 0, CONST, 1, 1, 0, BOOL, 1, 0, EQ, 0, 2, 1, 2]).is_err(), true);
+    }
+
+    #[test]
+    fn redeclare() {
+        let mut ctx = Ctx::new();
+        let u = ctx.api_ty_const("u").unwrap();
+        let bool_ = ctx.api_ty_bool();
+        let f = ctx.api_const("f", &[u], bool_).unwrap();
+        assert_eq!(ctx.api_const("f", &[u], bool_), Ok(f));
+        assert_eq!(ctx.api_const("f", &[bool_], bool_), Err(Error::Redeclared("f".to_string())));
+        assert_eq!(ctx.api_const("u", &[], bool_), Err(Error::Redeclared("u".to_string())));
+        assert_eq!(ctx.api_cstor("f", &[u], bool_), Err(Error::Redeclared("f".to_string())));
+        assert_eq!(ctx.api_ty_const("f"), Err(Error::Redeclared("f".to_string())));
+        assert_eq!(ctx.api_ty_const("u"), Ok(u));
+        let c = ctx.api_cstor("c", &[], u).unwrap();
+        assert!(ctx.is_cstor(&c));
+        assert_eq!(ctx.api_cstor("c", &[], u), Ok(c));
+        // argument types are checked
+        let a = ctx.api_const("a", &[], u).unwrap();
+        assert!(ctx.api_app(f, &[a]).is_ok());
+        assert!(matches!(ctx.api_app(f, &[bool_]), Err(Error::IllTyped(_))));
+        assert!(matches!(ctx.api_app(f, &[ctx.b.true_]), Err(Error::IllTyped(_))));
+        assert!(matches!(ctx.api_app(f, &[]), Err(Error::WrongArity{..})));
+    }
+
+    #[test]
+    fn dump_shared() {
+        // 2^40 leaves once expanded
+        let mut ctx = Ctx::new();
+        let u = ctx.api_ty_const("u").unwrap();
+        let g = ctx.api_const("g", &[u, u], u).unwrap();
+        let mut t = ctx.api_const("a", &[], u).unwrap();
+        for _ in 0 .. 40 { t = ctx.api_app(g, &[t, t]).unwrap() }
+        let mut out = String::new();
+        ctx.api_dump(&mut out);
+        assert!(out.len() < 10_000);
+        assert!(out.contains(&format!("#{}: g #{} #{}\n", t.idx(), t.idx()-1, t.idx()-1)));
+        assert_eq!(ctx.api_stats().n_terms, t.idx() as usize + 1);
+    }
+
+    #[test]
+    fn compact() {
+        let mut ctx = Ctx::new();
+        let u = ctx.api_ty_const("u").unwrap();
+        let v = ctx.api_ty_const("v").unwrap();
+        let f = ctx.api_const("f", &[u], u).unwrap();
+        let c = ctx.api_cstor("c", &[], u).unwrap();
+        ctx.api_const("a", &[], v).unwrap();
+        let fc = ctx.api_app(f, &[c]).unwrap();
+        let eq = ctx.api_eq(fc, c).unwrap();
+        // dead terms
+        let mut t = c;
+        for _ in 0 .. 100 { t = ctx.api_app(f, &[t]).unwrap() }
+
+        let (mut ctx2, roots) = ctx.api_compact(&[eq, fc]);
+        assert!(ctx2.n_terms() < ctx.n_terms());
+        assert_eq!(ctx2.api_stats().n_syms, 3); // `u`, `f` and `c`, but not `v` or `a`
+        // the same terms, rebuilt in `ctx2`
+        let u2 = ctx2.api_ty_const("u").unwrap();
+        let f2 = ctx2.api_const("f", &[u2], u2).unwrap();
+        let c2 = ctx2.api_cstor("c", &[], u2).unwrap();
+        let fc2 = ctx2.api_app(f2, &[c2]).unwrap();
+        assert_eq!(roots, vec![ctx2.api_eq(fc2, c2).unwrap(), fc2]);
+        assert!(ctx2.is_cstor(&c2));
+        // the id of a dead term is out of the new context's range
+        assert!(ctx2.check_term(t).is_err());
+    }
+}
@@ -0,0 +1,103 @@
+//! OCaml custom blocks with our own custom operations.
+//!
+//! `ocaml::Value::alloc_custom` only provides a finalizer. We also want
+//! polymorphic comparison and hashing to work (by identity), and to tell
+//! the GC how much memory lives outside of the OCaml heap.
+
+use {
+    std::{mem, ptr, os::raw::{c_char, c_int, c_void}},
+    ocaml::{Value, core::Value as RawValue},
+};
+
+/// Same layout as `struct custom_operations` (OCaml >= 4.08).
+#[repr(C)]
+pub struct CustomOperations {
+    pub identifier: *const c_char,
+    pub finalize: Option<extern "C" fn(RawValue)>,
+    pub compare: Option<extern "C" fn(RawValue, RawValue) -> c_int>,
+    pub hash: Option<extern "C" fn(RawValue) -> isize>,
+    pub serialize: Option<extern "C" fn(RawValue, *mut usize, *mut usize)>,
+    pub deserialize: Option<extern "C" fn(*mut c_void) -> usize>,
+    pub compare_ext: Option<extern "C" fn(RawValue, RawValue) -> c_int>,
+    pub fixed_length: *const c_void,
+}
+
+// only contains pointers to static data
+unsafe impl Sync for CustomOperations {}
+
+/// Operations for blocks whose first word is a pointer identifying them,
+/// as a `CustomOperations` literal. Such values can't be serialized.
+macro_rules! custom_ops {
+    ($id:expr, $finalize:expr) => {
+        custom::CustomOperations{
+            identifier: concat!($id, "\0").as_ptr() as *const std::os::raw::c_char,
+            finalize: Some($finalize),
+            compare: Some(custom::compare_ptr),
+            hash: Some(custom::hash_ptr),
+            serialize: None,
+            deserialize: None,
+            compare_ext: None,
+            fixed_length: std::ptr::null(),
+        }
+    }
+}
+
+extern "C" {
+    fn caml_alloc_custom_mem(ops: *const CustomOperations, size: usize, mem: usize) -> RawValue;
+    fn caml_alloc_dependent_memory(size: usize);
+    fn caml_free_dependent_memory(size: usize);
+}
+
+#[inline]
+unsafe fn ptr_of(v: RawValue) -> usize { *Value::new(v).custom_ptr_val::<usize>() }
+
+/// Compare by identity.
+pub extern "C" fn compare_ptr(v1: RawValue, v2: RawValue) -> c_int {
+    let (p1, p2) = unsafe { (ptr_of(v1), ptr_of(v2)) };
+    if p1 < p2 { -1 } else if p1 > p2 { 1 } else { 0 }
+}
+
+/// Hash by identity.
+pub extern "C" fn hash_ptr(v: RawValue) -> isize {
+    (unsafe { ptr_of(v) } >> 4) as isize
+}
+
+/// Allocate a custom block containing `data`, which owns about `mem`
+/// bytes outside of the OCaml heap.
+pub unsafe fn alloc<T>(ops: &'static CustomOperations, data: T, mem: usize) -> Value {
+    let v = Value::new(caml_alloc_custom_mem(ops, mem::size_of::<T>(), mem));
+    ptr::write(v.custom_ptr_val_mut::<T>(), data);
+    v
+}
+
+/// Minimum growth before telling the GC.
+const MIN_GROWTH: usize = 1 << 16;
+
+/// Memory owned by a custom block, as reported to the GC: `initial` bytes
+/// when allocated, then `extra` bytes as "dependent memory".
+#[repr(C)]
+#[derive(Copy,Clone,Debug)]
+pub struct Mem {
+    initial: usize,
+    extra: usize,
+}
+
+impl Mem {
+    /// `initial` must be the `mem` given to `alloc`.
+    pub fn new(initial: usize) -> Self { Mem{initial, extra: 0} }
+
+    /// The value now owns `size` bytes. Report significant growth.
+    pub unsafe fn grow(&mut self, size: usize) {
+        let cur = self.initial + self.extra;
+        if size > cur + MIN_GROWTH.max(cur / 8) {
+            caml_alloc_dependent_memory(size - cur);
+            self.extra += size - cur;
+        }
+    }
+
+    /// The value is freed: cancel what was reported by `grow`.
+    pub unsafe fn release(&mut self) {
+        if self.extra > 0 { caml_free_dependent_memory(self.extra) }
+        self.extra = 0;
+    }
+}
@@ -0,0 +1,197 @@
+//! DIMACS CNF input and output.
+
+use {
+    std::fmt::Write,
+    fxhash::FxHashSet,
+    crate::{
+        ctx::Ctx,
+        printer,
+        solver::{Solver, Lit, LitDecl},
+        error,
+    },
+    batsmt_solver::blit::SatLit,
+};
+
+/// Parse the DIMACS CNF problem `src` and add its clauses to `s`.
+///
+/// Each variable of the file gets a fresh literal, created in order.
+/// Returns these literals: variable `i` is mapped to the `i-1`-th one.
+///
+/// The whole input is parsed before `s` is modified, so on error `s`
+/// is left untouched. If a `p cnf <vars> <clauses>` header is present,
+/// the clauses must match it.
+pub fn load(s: &mut Solver, src: &str) -> Result<Vec<Lit>, String> {
+    let (n_vars, clauses) = parse(src)?;
+    let vars: Vec<Lit> = (0 .. n_vars).map(|_| s.api_make_lit()).collect();
+    let lit = |x: i32| {
+        let lit = vars[x.abs() as usize - 1];
+        if x > 0 { lit } else { Lit::unsafe_from_int(-lit.to_int()) }
+    };
+    let mut c = vec![];
+    for clause in clauses.iter() {
+        c.clear();
+        c.extend(clause.iter().map(|&x| lit(x)));
+        s.api_add_clause_from(&c).expect("the variables were just created");
+    }
+    Ok(vars)
+}
+
+/// Parse `src` into its number of variables and its clauses.
+fn parse(src: &str) -> Result<(usize, Vec<Vec<i32>>), String> {
+    let mut header: Option<(usize, usize)> = None;
+    let mut clauses: Vec<Vec<i32>> = vec![];
+    let mut cur: Vec<i32> = vec![];
+    let mut n_vars = 0;
+    for (i, line) in src.lines().enumerate() {
+        let line = line.trim();
+        if line.starts_with('c') || line.is_empty() {
+            continue
+        } else if line.starts_with('%') {
+            break // end marker used by some benchmark sets
+        } else if line.starts_with('p') {
+            if header.is_some() {
+                return Err(format!("line {}: duplicate `p` header", i+1))
+            }
+            let toks: Vec<&str> = line.split_whitespace().collect();
+            header = match &toks[..] {
+                ["p", "cnf", v, c] => match (v.parse(), c.parse()) {
+                    (Ok(v), Ok(c)) => Some((v, c)),
+                    _ => None,
+                },
+                _ => None,
+            };
+            if header.is_none() {
+                return Err(format!("line {}: invalid header `{}`", i+1, line))
+            }
+            continue
+        }
+        for tok in line.split_whitespace() {
+            let x: i32 = tok.parse().ok().filter(|&x| x != i32::MIN)
+                .ok_or_else(|| format!("line {}: invalid literal `{}`", i+1, tok))?;
+            if x == 0 {
+                clauses.push(std::mem::replace(&mut cur, vec![]));
+            } else {
+                n_vars = n_vars.max(x.abs() as usize);
+                cur.push(x);
+            }
+        }
+    }
+    if cur.len() > 0 {
+        clauses.push(cur); // last clause is missing its `0`
+    }
+    match header {
+        Some((v, _)) if v > i32::MAX as usize =>
+            Err(format!("too many variables in the header: {}", v)),
+        Some((v, _)) if n_vars > v =>
+            Err(format!("variable {} exceeds the {} declared in the header", n_vars, v)),
+        Some((_, c)) if clauses.len() != c =>
+            Err(format!("{} clauses, but {} declared in the header", clauses.len(), c)),
+        Some((v, _)) => Ok((v, clauses)),
+        None => Ok((n_vars, clauses)),
+    }
+}
+
+/// Print the clauses of `s` in DIMACS CNF format.
+///
+/// Variables keep their number in the solver. A comment section maps
+/// term literals back to their term, and lists the assumptions of the
+/// last call to `solve`. Fails if `s` did not keep its clauses.
+pub fn write(s: &Solver, ctx: &Ctx, out: &mut String) -> error::Result<()> {
+    let clauses = s.clauses()?;
+    let var = |l: &SatLit| Lit::new(*l).to_int().abs();
+    let n_vars =
+        clauses.iter().flat_map(|c| c.iter())
+        .chain(s.last_assumptions().iter())
+        .map(var).max().unwrap_or(0);
+
+    out.push_str("c generated by batsmt\n");
+    let mut seen = FxHashSet::default();
+    for d in s.lit_decls().iter() {
+        match d {
+            LitDecl::Term(t, lit) if seen.insert(lit.to_int()) => {
+                let _ = write!(out, "c lit {} = ", lit.to_int());
+                printer::pp_term(ctx, out, *t)?;
+                out.push('\n');
+            },
+            _ => (),
+        }
+    }
+    if s.last_assumptions().len() > 0 {
+        out.push_str("c assumptions:");
+        for &l in s.last_assumptions().iter() {
+            let _ = write!(out, " {}", Lit::new(l).to_int());
+        }
+        out.push('\n');
+    }
+    let _ = writeln!(out, "p cnf {} {}", n_vars, clauses.len());
+    for c in clauses.iter() {
+        for &l in c.iter() {
+            let _ = write!(out, "{} ", Lit::new(l).to_int());
+        }
+        out.push_str("0\n");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::Options;
+
+    fn solver(ctx: &mut Ctx) -> Solver {
+        Solver::new_with(ctx, Options{keep_clauses: true, ..Options::default()})
+    }
+
+    fn ints(s: &Solver) -> Vec<Vec<i32>> {
+        s.clauses().unwrap().iter()
+            .map(|c| c.iter().map(|&l| Lit::new(l).to_int()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let src = "c a comment\np cnf 3 3\n1 -2 0\n2 3 0\n-1\n-3 0\n";
+        let mut ctx = Ctx::new();
+        let mut s = solver(&mut ctx);
+        let vars = load(&mut s, src).unwrap();
+        assert_eq!(vars.len(), 3);
+        let mut out = String::new();
+        write(&s, &ctx, &mut out).unwrap();
+        assert!(out.contains("p cnf 3 3\n"));
+
+        let mut s2 = solver(&mut ctx);
+        load(&mut s2, &out).unwrap();
+        assert_eq!(ints(&s), ints(&s2));
+    }
+
+    #[test]
+    fn no_header() {
+        let mut ctx = Ctx::new();
+        let mut s = solver(&mut ctx);
+        let vars = load(&mut s, "1 2 0 -2 4").unwrap();
+        assert_eq!(vars.len(), 4);
+        assert_eq!(ints(&s).len(), 2);
+    }
+
+    #[test]
+    fn bad_input() {
+        let bad = [
+            "1 x 0",
+            "1 -2147483648 0",
+            "p cnf 2 1\n1 3 0",
+            "p cnf 2 2\n1 2 0",
+            "p cnf 2\n1 2 0",
+            "p cnf 2 1\np cnf 2 1\n1 2 0",
+            "p cnf 4294967296 0",
+        ];
+        for src in bad.iter() {
+            let mut ctx = Ctx::new();
+            let mut s = solver(&mut ctx);
+            let n_lits = s.api_n_lits();
+            assert!(load(&mut s, src).is_err(), "accepted {:?}", src);
+            // nothing was added, not even the clauses before the error
+            assert_eq!(s.api_n_lits(), n_lits);
+            assert_eq!(ints(&s).len(), 0);
+        }
+    }
+}
@@ -0,0 +1,68 @@
+//! Errors of the library API.
+
+use {
+    std::fmt,
+    batsmt_core::ast_u32::AST,
+};
+
+/// Errors of library operations.
+#[derive(Clone,Debug,PartialEq)]
+pub enum Error {
+    /// The term is not `true` or `false`.
+    NotABool(AST),
+    /// The term is not a constant.
+    NotAConst(AST),
+    /// The term is not an application.
+    NotAnApp(AST),
+    /// The term cannot be applied (it is not a declared function symbol).
+    NotAFunction(AST),
+    /// Wrong number of arguments in an application.
+    WrongArity{f: String, expected: usize, got: usize},
+    /// The symbol is already declared, with another signature or kind.
+    Redeclared(String),
+    /// The terms are not well typed (message describes how).
+    IllTyped(String),
+    /// The solver was used with another context than the one it was created with.
+    WrongCtx,
+    /// The operation is not supported.
+    Unsupported(&'static str),
+    /// The solver's clauses are needed, but it did not keep them.
+    ClausesNotKept,
+    /// The name cannot be printed as a SMT-LIB symbol.
+    InvalidSymbol(String),
+    /// No term has this index in the context.
+    UnknownTerm(u32),
+    /// The solver has no such literal.
+    UnknownLit(i32),
+    /// Node number `node` of an encoded DAG is not valid.
+    InvalidNode{node: usize, msg: &'static str},
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::NotABool(t) => write!(out, "term {} is not a boolean", t.idx()),
+            Error::NotAConst(t) => write!(out, "term {} is not a constant", t.idx()),
+            Error::NotAnApp(t) => write!(out, "term {} is not an application", t.idx()),
+            Error::NotAFunction(t) => write!(out, "cannot apply term {}", t.idx()),
+            Error::WrongArity{f, expected, got} => {
+                write!(out, "wrong arity for {} (expect {} args, got {})", f, expected, got)
+            },
+            Error::Redeclared(s) => write!(out, "{} is already declared differently", s),
+            Error::IllTyped(msg) => write!(out, "ill-typed term: {}", msg),
+            Error::WrongCtx => write!(out, "solver used with a context other than its own"),
+            Error::Unsupported(what) => write!(out, "unsupported: {}", what),
+            Error::InvalidSymbol(s) => write!(out, "{:?} is not a valid SMT-LIB symbol", s),
+            Error::ClausesNotKept => {
+                write!(out, "the solver did not keep its clauses (see option keep_clauses)")
+            },
+            Error::UnknownTerm(i) => write!(out, "no term {} in this context", i),
+            Error::UnknownLit(l) => write!(out, "no literal {} in this solver", l),
+            Error::InvalidNode{node, msg} => write!(out, "invalid node {}: {}", node, msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,111 @@
+use {
+    std::{
+        thread, panic,
+        time::{Duration, Instant},
+        sync::{Arc, Mutex, Condvar},
+    },
+    crate::{ctx::Ctx, solver::Solver},
+};
+
+/// State of a background search.
+#[derive(Copy,Clone,Debug,Eq,PartialEq)]
+pub enum Status {
+    Running,
+    Done(bool),
+    Detached,
+    Failed, // the search panicked
+}
+
+struct Shared {
+    status: Mutex<Status>,
+    cond: Condvar,
+}
+
+/// A search running on its own thread.
+///
+/// The solver and context are marked busy until the search returns.
+/// batsmt-solver cannot interrupt a search, so a job can only be detached:
+/// the search keeps running until it finishes, and the solver stays busy
+/// until then.
+pub struct Job {
+    shared: Arc<Shared>,
+}
+
+// raw pointer we can move to the worker thread.
+struct SendPtr<T>(*mut T);
+unsafe impl<T> Send for SendPtr<T> {}
+
+impl Job {
+    /// Start solving on a new thread.
+    ///
+    /// Safety: `solver` and `ctx` must already be marked busy. The worker
+    /// releases them once the search is over, or frees them if they were
+    /// orphaned in the meantime.
+    pub unsafe fn spawn(solver: &mut Solver, ctx: &mut Ctx) -> Self {
+        let shared = Arc::new(Shared{
+            status: Mutex::new(Status::Running), cond: Condvar::new(),
+        });
+        let sh = shared.clone();
+        let s = SendPtr(solver as *mut Solver);
+        let c = SendPtr(ctx as *mut Ctx);
+        thread::spawn(move || {
+            let (s, c) = (s, c);
+            let r = panic::catch_unwind(panic::AssertUnwindSafe(|| unsafe {
+                (*s.0).api_solve(&mut *c.0)
+            }));
+            // release them even after a panic, so OCaml can still delete them
+            unsafe {
+                if ! Solver::busy_of(s.0).release() { drop(Box::from_raw(s.0)) }
+                if ! Ctx::busy_of(c.0).release() { drop(Box::from_raw(c.0)) }
+            }
+            let st = match r {
+                Ok(r) => Status::Done(r),
+                Err(_) => Status::Failed,
+            };
+            let mut status = sh.status.lock().unwrap();
+            if *status == Status::Running {
+                *status = st;
+            }
+            sh.cond.notify_all();
+        });
+        Job{shared}
+    }
+
+    /// Current status, without blocking.
+    pub fn poll(&self) -> Status {
+        *self.shared.status.lock().unwrap()
+    }
+
+    /// Wait until the job is not running anymore, or until `timeout` expires.
+    pub fn wait(&self, timeout: Option<Duration>) -> Status {
+        let deadline = timeout.map(|d| Instant::now() + d);
+        let mut status = self.shared.status.lock().unwrap();
+        while *status == Status::Running {
+            match deadline {
+                None => {
+                    status = self.shared.cond.wait(status).unwrap();
+                },
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline { break }
+                    status = self.shared.cond.wait_timeout(status, deadline - now).unwrap().0;
+                },
+            }
+        }
+        *status
+    }
+
+    /// Stop waiting for the job, without stopping the search.
+    /// Does nothing if it's already done.
+    pub fn detach(&self) {
+        let mut status = self.shared.status.lock().unwrap();
+        if *status == Status::Running {
+            *status = Status::Detached;
+            self.shared.cond.notify_all();
+        }
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) { self.detach() }
+}
@@ -0,0 +1,251 @@
+use {
+    std::{thread, sync::{Arc, Mutex, Condvar, mpsc, atomic::{AtomicBool, Ordering}}},
+    fxhash::FxHashMap,
+    crate::{
+        ctx::Ctx,
+        solver::{Solver, Options, Lit, LitDecl},
+        rng::Rng,
+        copy::{self, Node},
+        error::Result,
+    },
+};
+
+/// A self-contained copy of a solver's problem.
+///
+/// It can be sent to another thread and loaded there into a fresh
+/// context and solver.
+#[derive(Clone)]
+pub struct Problem {
+    nodes: Vec<Node>,
+    lits: Vec<(i32, Option<usize>)>, // literal, and its term if any
+    clauses: Vec<Vec<i32>>,
+    assumptions: Vec<i32>,
+    opts: Options,
+}
+
+/// Result of a portfolio run.
+pub struct Answer {
+    pub sat: bool,
+    pub winner: usize, // index of the worker that answered
+    pub core: Vec<Lit>, // unsat core, in terms of the original literals
+}
+
+// maps variables of the original solver to literals of a copy
+struct LitMap {
+    fwd: FxHashMap<i32, i32>,
+    back: FxHashMap<i32, i32>,
+}
+
+impl LitMap {
+    fn add(&mut self, old: i32, new: i32) {
+        let (old, new) = if old < 0 { (-old, -new) } else { (old, new) };
+        self.fwd.insert(old, new);
+        self.back.insert(new.abs(), if new < 0 { -old } else { old });
+    }
+
+    fn map(&self, lit: i32) -> Lit {
+        let l = self.fwd[&lit.abs()];
+        Lit::unsafe_from_int(if lit < 0 { -l } else { l })
+    }
+
+    fn unmap(&self, lit: Lit) -> Lit {
+        let lit = lit.to_int();
+        let l = self.back[&lit.abs()];
+        Lit::unsafe_from_int(if lit < 0 { -l } else { l })
+    }
+}
+
+impl Problem {
+    /// Copy the clauses and literals of `s`, to be solved under `assumptions`.
+    ///
+    /// Fails if `s` did not keep its clauses.
+    pub fn new(s: &Solver, ctx: &Ctx, assumptions: &[Lit]) -> Result<Self> {
+        let clauses =
+            s.clauses()?.iter()
+            .map(|c| c.iter().map(|&l| Lit::new(l).to_int()).collect())
+            .collect();
+        let mut b = copy::Builder::new(ctx);
+        let lits =
+            s.lit_decls().iter()
+            .map(|d| match d {
+                LitDecl::Fresh(lit) => (lit.to_int(), None),
+                LitDecl::Term(t, lit) => (lit.to_int(), Some(b.node(*t))),
+            })
+            .collect();
+        let assumptions = assumptions.iter().map(|l| l.to_int()).collect();
+        let opts = s.options().clone();
+        Ok(Problem{nodes: b.into_nodes(), lits, clauses, assumptions, opts})
+    }
+
+    /// Options of worker number `i`, which uses `seed`.
+    ///
+    /// A non-zero seed shuffles the order of literals and clauses, which
+    /// changes the variable order and the watched literals. Odd workers
+    /// also flip `auto_simplify`.
+    pub fn worker_options(&self, i: usize, seed: u64) -> Options {
+        let auto_simplify = self.opts.auto_simplify ^ (i % 2 == 1);
+        Options{seed, auto_simplify, keep_clauses: false}
+    }
+
+    /// Solve in the current thread, as worker number `i` with `seed`.
+    ///
+    /// Returns the result and the unsat core, in terms of the original
+    /// literals.
+    pub fn solve_as_worker(&self, i: usize, seed: u64) -> (bool, Vec<Lit>) {
+        self.run(self.worker_options(i, seed), &AtomicBool::new(false))
+            .expect("never stopped")
+    }
+
+    /// Load into a fresh context and solver with options `opts`.
+    ///
+    /// Returns `None` if `stop` is set in the meantime.
+    fn load(&self, opts: Options, stop: &AtomicBool) -> Option<(Ctx, Solver, LitMap)> {
+        let mut ctx = Ctx::new();
+        let terms = copy::build(&self.nodes, &mut ctx);
+
+        let mut rng = Rng::new(opts.seed);
+        let mut lits = self.lits.clone();
+        let mut clauses = self.clauses.clone();
+        if opts.seed != 0 {
+            rng.shuffle(&mut lits);
+            rng.shuffle(&mut clauses);
+            for c in clauses.iter_mut() { rng.shuffle(c) }
+        }
+
+        let mut s = Solver::new_with(&mut ctx, opts);
+        let mut map = LitMap{fwd: FxHashMap::default(), back: FxHashMap::default()};
+        for &(lit, t) in lits.iter() {
+            let new_lit = match t {
+                None => s.api_make_lit(),
+                Some(i) => s.api_make_term_lit(&mut ctx, terms[i]),
+            };
+            map.add(lit, new_lit.to_int());
+        }
+        for (i, c) in clauses.iter().enumerate() {
+            if i % 1024 == 0 && stop.load(Ordering::Relaxed) { return None }
+            for &lit in c.iter() { s.api_add_clause_lit(map.map(lit)) }
+            s.api_add_clause();
+        }
+        Some((ctx, s, map))
+    }
+
+    // load and solve, returning the result and unsat core, or `None` if
+    // stopped before the search started
+    fn run(&self, opts: Options, stop: &AtomicBool) -> Option<(bool, Vec<Lit>)> {
+        let (mut ctx, mut s, map) = self.load(opts, stop)?;
+        for &lit in self.assumptions.iter() { s.api_add_assumption(map.map(lit)) }
+        if stop.load(Ordering::Relaxed) { return None }
+        let sat = s.api_solve(&mut ctx);
+        let core = if sat { vec![] } else {
+            s.api_unsat_core().iter().map(|&l| map.unmap(Lit::new(l))).collect()
+        };
+        Some((sat, core))
+    }
+}
+
+/// Maximum number of worker threads alive at once, over all calls to `solve`.
+pub const MAX_WORKERS: usize = 64;
+
+// number of worker threads alive, including those of earlier calls that
+// are still searching
+static WORKERS: (Mutex<usize>, Condvar) = (Mutex::new(0), Condvar::new());
+
+// decrements `WORKERS` when the worker exits, even by panicking
+struct WorkerGuard;
+
+impl Drop for WorkerGuard {
+    fn drop(&mut self) {
+        *WORKERS.0.lock().unwrap() -= 1;
+        WORKERS.1.notify_all();
+    }
+}
+
+/// Number of worker threads still alive.
+pub fn n_workers() -> usize { *WORKERS.0.lock().unwrap() }
+
+/// Solve `p` with one worker thread per seed, and return the first answer.
+///
+/// Once an answer is found, the other workers are told to stop. They check
+/// this while loading their copy and before searching; a worker already
+/// searching finishes in the background and its answer is dropped.
+/// At most `MAX_WORKERS` workers are alive at once: when that many are
+/// still running, spawning the next one waits until one exits.
+/// Returns `None` if every worker failed.
+pub fn solve(p: Problem, seeds: &[u64]) -> Option<Answer> {
+    let p = Arc::new(p);
+    let stop = Arc::new(AtomicBool::new(false));
+    let (tx, rx) = mpsc::channel();
+    for (winner, &seed) in seeds.iter().enumerate() {
+        {
+            let mut n = WORKERS.0.lock().unwrap();
+            while *n >= MAX_WORKERS { n = WORKERS.1.wait(n).unwrap() }
+            *n += 1;
+        }
+        let p = p.clone();
+        let stop = stop.clone();
+        let tx = tx.clone();
+        let opts = p.worker_options(winner, seed);
+        thread::spawn(move || {
+            let _guard = WorkerGuard;
+            if let Some((sat, core)) = p.run(opts, &stop) {
+                let _ = tx.send(Answer{sat, winner, core}); // might be too late
+            }
+        });
+    }
+    drop(tx);
+    let ans = rx.recv().ok();
+    stop.store(true, Ordering::Relaxed);
+    ans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn neg(lit: Lit) -> Lit { Lit::unsafe_from_int(-lit.to_int()) }
+
+    // `a ∨ b`, `¬a ∨ b`, with `b` a term literal
+    fn problem(keep_clauses: bool) -> (Ctx, Solver, Lit, Lit) {
+        let mut ctx = Ctx::new();
+        let bool_ = ctx.api_ty_bool();
+        let b = ctx.api_const("b", &[], bool_).unwrap();
+        let mut s = Solver::new_with(&mut ctx, Options{keep_clauses, ..Options::default()});
+        let a = s.api_make_lit();
+        let b = s.api_make_term_lit(&mut ctx, b);
+        s.api_add_clause_from(&[a, b]).unwrap();
+        s.api_add_clause_from(&[neg(a), b]).unwrap();
+        (ctx, s, a, b)
+    }
+
+    #[test]
+    fn needs_clauses() {
+        let (ctx, s, _, _) = problem(false);
+        assert!(Problem::new(&s, &ctx, &[]).is_err());
+    }
+
+    #[test]
+    fn same_answer() {
+        let (ctx, s, a, b) = problem(true);
+        let seeds = [0, 1, 2, 3];
+        let ans = solve(Problem::new(&s, &ctx, &[a]).unwrap(), &seeds).unwrap();
+        assert!(ans.sat);
+        assert!(ans.winner < seeds.len());
+        let ans = solve(Problem::new(&s, &ctx, &[a, neg(b)]).unwrap(), &seeds).unwrap();
+        assert!(! ans.sat);
+        // the core is in terms of the original literals
+        let core: Vec<i32> = ans.core.iter().map(|l| l.to_int()).collect();
+        assert!(core.iter().all(|&l| l == a.to_int() || l == -b.to_int()));
+        assert!(core.contains(&-b.to_int()));
+    }
+
+    #[test]
+    fn bounded_workers() {
+        let (ctx, s, a, _) = problem(true);
+        let seeds: Vec<u64> = (0 .. MAX_WORKERS as u64).collect();
+        for _ in 0 .. 3 {
+            let ans = solve(Problem::new(&s, &ctx, &[a]).unwrap(), &seeds).unwrap();
+            assert!(ans.sat);
+            assert!(n_workers() <= MAX_WORKERS);
+        }
+    }
+}
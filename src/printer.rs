@@ -0,0 +1,451 @@
+//! SMT-LIB 2.6 printer.
+//!
+//! Prints the declarations of a `Ctx`, its terms (with `let` for
+//! subterms that occur several times), and the whole problem of a `Solver`.
+
+use {
+    std::fmt::Write,
+    fxhash::{FxHashMap, FxHashSet},
+    batsmt_core::{AstView, Manager, ast_u32::AST},
+    crate::{
+        ctx::{Ctx, SymKind},
+        solver::{Solver, Lit, LitDecl},
+        error::{Error, Result},
+    },
+};
+
+/// Print `s` as a SMT-LIB symbol, quoting it if needed.
+///
+/// Fails if `s` contains `|` or `\`, which cannot occur in a symbol.
+pub(crate) fn pp_symbol(out: &mut String, s: &str) -> Result<()> {
+    let simple =
+        s.len() > 0 &&
+        ! s.as_bytes()[0].is_ascii_digit() &&
+        s.chars().all(|c| c.is_ascii_alphanumeric() || "~!@$%^&*_-+=<>.?/".contains(c));
+    if simple {
+        out.push_str(s)
+    } else if s.contains(|c| c == '|' || c == '\\') {
+        return Err(Error::InvalidSymbol(s.to_string()))
+    } else {
+        let _ = write!(out, "|{}|", s);
+    }
+    Ok(())
+}
+
+// name of a declared symbol
+fn sym_name(ctx: &Ctx, t: AST) -> &str {
+    ctx.api_const_get_name(t).expect("symbol is not a constant")
+}
+
+/// A prefix for generated names, starting with `base`, such that no
+/// symbol of `ctx` starts with it.
+fn fresh_prefix(ctx: &Ctx, base: &str) -> String {
+    let names: Vec<&str> = ctx.symbols().iter().map(|(t,_)| sym_name(ctx, *t)).collect();
+    let mut p = base.to_string();
+    while names.iter().any(|n| n.starts_with(&p)) { p.push('_') }
+    p
+}
+
+fn pp_sort(ctx: &Ctx, out: &mut String, ty: AST) -> Result<()> {
+    if ty == ctx.b.bool_ { out.push_str("Bool"); Ok(()) }
+    else { pp_symbol(out, sym_name(ctx, ty)) }
+}
+
+/// Print declarations for every symbol of `ctx`.
+///
+/// Sorts with constructors are printed as a single `declare-datatypes`,
+/// with generated selector names.
+/// Fails if a name cannot be printed (see `pp_symbol`).
+pub fn pp_decls(ctx: &Ctx, out: &mut String) -> Result<()> {
+    let syms = ctx.symbols();
+    // constructors of each datatype, in order
+    let mut datatypes: Vec<(AST, Vec<(AST, &[AST])>)> = vec![];
+    for (t, k) in syms.iter() {
+        match k {
+            SymKind::Const{args, ret} if ctx.is_cstor(t) => {
+                match datatypes.iter_mut().find(|(ty,_)| *ty == *ret) {
+                    Some((_, cs)) => cs.push((*t, &args[..])),
+                    None => datatypes.push((*ret, vec![(*t, &args[..])])),
+                }
+            },
+            _ => (),
+        }
+    }
+
+    for (t, k) in syms.iter() {
+        match k {
+            SymKind::Ty if ! datatypes.iter().any(|(ty,_)| ty == t) => {
+                out.push_str("(declare-sort ");
+                pp_symbol(out, sym_name(ctx, *t))?;
+                out.push_str(" 0)\n");
+            },
+            _ => (),
+        }
+    }
+
+    if datatypes.len() > 0 {
+        let sel = fresh_prefix(ctx, "_sel_");
+        out.push_str("(declare-datatypes (");
+        for (i, (ty, _)) in datatypes.iter().enumerate() {
+            if i > 0 { out.push(' ') }
+            out.push('(');
+            pp_sort(ctx, out, *ty)?;
+            out.push_str(" 0)");
+        }
+        out.push_str(") (");
+        for (i, (_, cs)) in datatypes.iter().enumerate() {
+            if i > 0 { out.push_str("\n  ") }
+            out.push('(');
+            for (j, (c, args)) in cs.iter().enumerate() {
+                if j > 0 { out.push(' ') }
+                let name = sym_name(ctx, *c);
+                out.push('(');
+                pp_symbol(out, name)?;
+                for (k, ty) in args.iter().enumerate() {
+                    out.push_str(" (");
+                    pp_symbol(out, &format!("{}{}_{}", sel, k, name))?;
+                    out.push(' ');
+                    pp_sort(ctx, out, *ty)?;
+                    out.push(')');
+                }
+                out.push(')');
+            }
+            out.push(')');
+        }
+        out.push_str("))\n");
+    }
+
+    for (t, k) in syms.iter() {
+        match k {
+            SymKind::Const{args, ret} if ! ctx.is_cstor(t) => {
+                out.push_str("(declare-fun ");
+                pp_symbol(out, sym_name(ctx, *t))?;
+                out.push_str(" (");
+                for (i, ty) in args.iter().enumerate() {
+                    if i > 0 { out.push(' ') }
+                    pp_sort(ctx, out, *ty)?;
+                }
+                out.push_str(") ");
+                pp_sort(ctx, out, *ret)?;
+                out.push_str(")\n");
+            },
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// Term printer, sharing subterms that occur several times.
+///
+/// Terms can be arbitrarily deep, so nothing here recurses.
+struct TermPrinter<'a> {
+    ctx: &'a Ctx,
+    n_parents: FxHashMap<AST, usize>,
+    order: Vec<AST>, // compound subterms, in post-order
+    names: FxHashMap<AST, String>,
+}
+
+// what's left to print, see `TermPrinter::pp_rec`
+enum Tok {
+    Term(AST),
+    Str(&'static str),
+}
+
+impl<'a> TermPrinter<'a> {
+    fn new(ctx: &'a Ctx) -> Self {
+        TermPrinter{
+            ctx, n_parents: FxHashMap::default(), order: vec![],
+            names: FxHashMap::default(),
+        }
+    }
+
+    // count parents of compound subterms of `t`, and one for `t`
+    fn count(&mut self, t: AST) {
+        let ctx = self.ctx;
+        let mut stack = vec![(t, false)];
+        while let Some((u, args_done)) = stack.pop() {
+            if args_done { self.order.push(u); continue }
+            if let AstView::App{f, args} = ctx.m.view(&u) {
+                let n = self.n_parents.entry(u).or_insert(0);
+                *n += 1;
+                if *n > 1 { continue } // already traversed
+                stack.push((u, true));
+                for v in args.iter().rev() { stack.push((*v, false)) }
+                stack.push((*f, false));
+            }
+        }
+    }
+
+    // subterms to name, in order
+    fn shared(&mut self) -> Vec<AST> {
+        let order = std::mem::replace(&mut self.order, vec![]);
+        order.into_iter().filter(|u| self.n_parents[u] > 1).collect()
+    }
+
+    fn pp_rec(&self, out: &mut String, t: AST) -> Result<()> {
+        let ctx = self.ctx;
+        let mut stack = vec![Tok::Term(t)];
+        while let Some(tok) = stack.pop() {
+            let t = match tok {
+                Tok::Str(s) => { out.push_str(s); continue },
+                Tok::Term(t) => t,
+            };
+            if let Some(name) = self.names.get(&t) {
+                out.push_str(name);
+            } else if t == ctx.b.true_ {
+                out.push_str("true")
+            } else if t == ctx.b.false_ {
+                out.push_str("false")
+            } else {
+                match ctx.m.view(&t) {
+                    AstView::App{f, args} => {
+                        out.push('(');
+                        stack.push(Tok::Str(")"));
+                        for u in args.iter().rev() {
+                            stack.push(Tok::Term(*u));
+                            stack.push(Tok::Str(" "));
+                        }
+                        if *f == ctx.b.not_ { out.push_str("not") }
+                        else if *f == ctx.b.eq { out.push_str("=") }
+                        else if *f == ctx.b.distinct { out.push_str("distinct") }
+                        else { stack.push(Tok::Term(*f)) }
+                    },
+                    AstView::Const(s) => pp_symbol(out, s)?,
+                    AstView::Index(i) => { let _ = write!(out, "{}", i); },
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // print `t`, with a `let` for each shared subterm
+    fn pp(mut self, out: &mut String, t: AST) -> Result<()> {
+        self.count(t);
+        let prefix = fresh_prefix(self.ctx, "_let_");
+        let mut n_let = 0;
+        for u in self.shared() {
+            if u == t { continue }
+            n_let += 1;
+            let name = format!("{}{}", prefix, n_let);
+            let _ = write!(out, "(let (({} ", name);
+            self.pp_rec(out, u)?;
+            out.push_str(")) ");
+            self.names.insert(u, name);
+        }
+        self.pp_rec(out, t)?;
+        for _ in 0 .. n_let { out.push(')') }
+        Ok(())
+    }
+
+    // print a `define-fun` for each subterm shared by the terms counted
+    // so far, to be used by later calls to `pp_rec`
+    fn define_shared(&mut self, out: &mut String) -> Result<()> {
+        let prefix = fresh_prefix(self.ctx, "_def_");
+        for (i, u) in self.shared().into_iter().enumerate() {
+            let name = format!("{}{}", prefix, i+1);
+            let _ = write!(out, "(define-fun {} () ", name);
+            let ty = self.ctx.m.ty(&u).expect("untyped term");
+            pp_sort(self.ctx, out, ty)?;
+            out.push(' ');
+            self.pp_rec(out, u)?;
+            out.push_str(")\n");
+            self.names.insert(u, name);
+        }
+        Ok(())
+    }
+}
+
+/// Print a term, using `let` for shared subterms.
+/// Fails if a name cannot be printed (see `pp_symbol`).
+pub fn pp_term(ctx: &Ctx, out: &mut String, t: AST) -> Result<()> {
+    TermPrinter::new(ctx).pp(out, t)
+}
+
+/// Print the whole problem of `s`: declarations, clauses, and a final
+/// `check-sat-assuming` with the assumptions of its last call to `solve`.
+///
+/// Pure boolean literals are declared as fresh constants, and subterms
+/// that occur several times are named by a `define-fun`.
+/// Fails if `s` did not keep its clauses, or if a name cannot be printed.
+pub fn pp_solver(s: &Solver, ctx: &Ctx, out: &mut String) -> Result<()> {
+    let clauses = s.clauses()?;
+    let has_cstors = ctx.symbols().iter().any(|(t,_)| ctx.is_cstor(t));
+    let _ = writeln!(out, "(set-logic {})", if has_cstors { "QF_UFDT" } else { "QF_UF" });
+    pp_decls(ctx, out)?;
+
+    // map each variable to its term, if any
+    let mut terms: FxHashMap<i32, (AST, bool)> = FxHashMap::default();
+    for d in s.lit_decls().iter() {
+        if let LitDecl::Term(t, lit) = d {
+            let lit = lit.to_int();
+            terms.insert(lit.abs(), (*t, lit > 0));
+        }
+    }
+    let b = fresh_prefix(ctx, "_b");
+    let mut fresh: FxHashSet<i32> = FxHashSet::default();
+    let mut p = TermPrinter::new(ctx);
+    let lits =
+        clauses.iter().flat_map(|c| c.iter())
+        .chain(s.last_assumptions().iter())
+        .map(|&l| Lit::new(l).to_int().abs());
+    for v in lits {
+        match terms.get(&v) {
+            Some(&(t, _)) => p.count(t),
+            None if fresh.insert(v) => { let _ = writeln!(out, "(declare-const {}{} Bool)", b, v); },
+            None => (),
+        }
+    }
+    p.define_shared(out)?;
+
+    let pp_lit = |out: &mut String, lit: Lit| -> Result<()> {
+        let lit = lit.to_int();
+        match terms.get(&lit.abs()) {
+            Some(&(t, sign)) => {
+                if (lit > 0) == sign { p.pp_rec(out, t)? }
+                else { out.push_str("(not "); p.pp_rec(out, t)?; out.push(')') }
+            },
+            None if lit > 0 => { let _ = write!(out, "{}{}", b, lit); },
+            None => { let _ = write!(out, "(not {}{})", b, -lit); },
+        }
+        Ok(())
+    };
+
+    for c in clauses.iter() {
+        match &c[..] {
+            [] => out.push_str("(assert false)\n"),
+            [l] => {
+                out.push_str("(assert ");
+                pp_lit(out, Lit::new(*l))?;
+                out.push_str(")\n");
+            },
+            _ => {
+                out.push_str("(assert (or");
+                for &l in c.iter() {
+                    out.push(' ');
+                    pp_lit(out, Lit::new(l))?;
+                }
+                out.push_str("))\n");
+            },
+        }
+    }
+    if s.last_assumptions().len() > 0 {
+        out.push_str("(check-sat-assuming (");
+        for (i, &l) in s.last_assumptions().iter().enumerate() {
+            if i > 0 { out.push(' ') }
+            pp_lit(out, Lit::new(l))?;
+        }
+        out.push_str("))\n");
+    } else {
+        out.push_str("(check-sat)\n");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{smtlib, solver::Options};
+
+    fn neg(lit: Lit) -> Lit { Lit::unsafe_from_int(-lit.to_int()) }
+
+    #[test]
+    fn symbols() {
+        let mut out = String::new();
+        for s in ["a.b", "a b", "1x"].iter() {
+            pp_symbol(&mut out, s).unwrap();
+            out.push(' ');
+        }
+        assert_eq!(out, "a.b |a b| |1x| ");
+        assert!(pp_symbol(&mut out, "a|b").is_err());
+        assert!(pp_symbol(&mut out, "a\\b").is_err());
+    }
+
+    // `f(f(a)) = b`, `f(f(a)) = c`, and `b ≠ c` if `unsat`, with constants
+    // named like the printer's generated names
+    fn problem(unsat: bool) -> (Ctx, Solver) {
+        let mut ctx = Ctx::new();
+        let u = ctx.api_ty_const("U").unwrap();
+        let f = ctx.api_const("f", &[u], u).unwrap();
+        let a = ctx.api_const("_b1", &[], u).unwrap();
+        let b = ctx.api_const("_def_1", &[], u).unwrap();
+        let c = ctx.api_const("_let_1", &[], u).unwrap();
+        let fa = ctx.api_app(f, &[a]).unwrap();
+        let ffa = ctx.api_app(f, &[fa]).unwrap();
+        let e1 = ctx.api_eq(ffa, b).unwrap();
+        let e2 = ctx.api_eq(ffa, c).unwrap();
+        let e3 = ctx.api_eq(b, c).unwrap();
+        let mut s = Solver::new_with(&mut ctx, Options{keep_clauses: true, ..Options::default()});
+        let x = s.api_make_lit();
+        let l1 = s.api_make_term_lit(&mut ctx, e1);
+        let l2 = s.api_make_term_lit(&mut ctx, e2);
+        let l3 = s.api_make_term_lit(&mut ctx, e3);
+        s.api_add_clause_from(&[x, l1]).unwrap();
+        s.api_add_clause_from(&[neg(x), l1]).unwrap();
+        s.api_add_clause_from(&[l2]).unwrap();
+        if unsat { s.api_add_clause_from(&[neg(l3)]).unwrap() }
+        (ctx, s)
+    }
+
+    #[test]
+    fn solver_round_trip() {
+        for &unsat in [false, true].iter() {
+            let (ctx, s) = problem(unsat);
+            let mut out = String::new();
+            pp_solver(&s, &ctx, &mut out).unwrap();
+            let res = smtlib::parse_and_run(&mut Ctx::new(), &out);
+            assert_eq!(res, if unsat { "unsat\n" } else { "sat\n" }, "for {}", out);
+            // `f(f(a))` is shared by two clauses, and printed once
+            assert_eq!(out.matches("(f (f").count(), 1, "for {}", out);
+        }
+    }
+
+    #[test]
+    fn needs_clauses() {
+        let mut ctx = Ctx::new();
+        let s = Solver::new(&mut ctx);
+        assert!(pp_solver(&s, &ctx, &mut String::new()).is_err());
+    }
+
+    #[test]
+    fn decls_round_trip() {
+        let mut ctx = Ctx::new();
+        let u = ctx.api_ty_const("U").unwrap();
+        let d = ctx.api_ty_const("D").unwrap();
+        let mk = ctx.api_const("mk", &[u], d).unwrap();
+        ctx.api_set_is_cstor(mk).unwrap();
+        ctx.api_const("_sel_0_mk", &[], u).unwrap();
+        let mut out = String::new();
+        pp_decls(&ctx, &mut out).unwrap();
+        assert!(out.contains("(_sel__0_mk U)"), "for {}", out);
+        out.push_str("(check-sat)");
+        assert_eq!(smtlib::parse_and_run(&mut Ctx::new(), &out), "sat\n", "for {}", out);
+    }
+
+    #[test]
+    fn term_lets() {
+        let mut ctx = Ctx::new();
+        let u = ctx.api_ty_const("U").unwrap();
+        let f = ctx.api_const("g", &[u, u], u).unwrap();
+        let a = ctx.api_const("a", &[], u).unwrap();
+        let ga = ctx.api_app(f, &[a, a]).unwrap();
+        let t = ctx.api_app(f, &[ga, ga]).unwrap();
+        let mut out = String::new();
+        pp_term(&ctx, &mut out, t).unwrap();
+        assert_eq!(out, "(let ((_let_1 (g a a))) (g _let_1 _let_1))");
+
+        let bad = ctx.api_const("x|y", &[], u).unwrap();
+        assert!(pp_term(&ctx, &mut String::new(), bad).is_err());
+    }
+
+    #[test]
+    fn deep_term() {
+        let mut ctx = Ctx::new();
+        let u = ctx.api_ty_const("U").unwrap();
+        let f = ctx.api_const("f", &[u], u).unwrap();
+        let mut t = ctx.api_const("a", &[], u).unwrap();
+        for _ in 0 .. 200_000 { t = ctx.api_app(f, &[t]).unwrap() }
+        let mut out = String::new();
+        pp_term(&ctx, &mut out, t).unwrap();
+        assert_eq!(out.len(), "(f ".len() * 200_000 + 1 + 200_000);
+    }
+}
@@ -0,0 +1,827 @@
+//! Replay of traces recorded by `trace`.
+//!
+//! Each call of the trace is run again on fresh contexts and solvers,
+//! mapping recorded handles, terms and literals to the new ones. The
+//! result of every `solve` (and of SMT-LIB scripts) is checked against
+//! the recorded one.
+
+use {
+    std::{fmt, panic::{self, AssertUnwindSafe}},
+    fxhash::FxHashMap,
+    batsmt_core::ast_u32::AST,
+    crate::{
+        ctx::{Ctx, AstKind},
+        smtlib, dimacs, portfolio,
+        solver::{Solver, Lit, Options},
+    },
+};
+
+/// A recorded value.
+#[derive(Clone,Debug,PartialEq)]
+pub enum Val {
+    Unit,
+    Int(i64),
+    Bool(bool),
+    Float(f64),
+    Str(String),
+    Ints(Vec<i64>),
+    Strs(Vec<String>),
+    Tuple(Vec<Val>),
+    Handle(char, usize),
+}
+
+/// A recorded call.
+#[derive(Clone,Debug)]
+pub struct Call {
+    pub line: usize, // in the trace file, from 1
+    pub name: String,
+    pub args: Vec<Val>,
+    pub res: Val,
+    pub text: String, // original line
+}
+
+/// Result of replaying a trace.
+#[derive(Clone,Debug,PartialEq)]
+pub enum Outcome {
+    /// Every call ran, and `n_checks` results matched.
+    Ok{n_checks: usize},
+    /// The call at this line returned another result.
+    Mismatch{line: usize, msg: String},
+    /// The call at this line panicked.
+    Crash{line: usize, msg: String},
+    /// The trace is not well formed (unknown handle, term or literal…).
+    Invalid{line: usize, msg: String},
+}
+
+impl Outcome {
+    /// Did the replay exhibit a bug?
+    pub fn is_failure(&self) -> bool {
+        match self { Outcome::Mismatch{..} | Outcome::Crash{..} => true, _ => false }
+    }
+
+    fn line(&self) -> Option<usize> {
+        match self {
+            Outcome::Ok{..} => None,
+            Outcome::Mismatch{line,..} | Outcome::Crash{line,..}
+                | Outcome::Invalid{line,..} => Some(*line),
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Outcome::Ok{n_checks} => write!(out, "ok ({} results checked)", n_checks),
+            Outcome::Mismatch{line, msg} => write!(out, "line {}: mismatch: {}", line, msg),
+            Outcome::Crash{line, msg} => write!(out, "line {}: crash: {}", line, msg),
+            Outcome::Invalid{line, msg} => write!(out, "line {}: invalid trace: {}", line, msg),
+        }
+    }
+}
+
+/// Parse a trace.
+pub fn parse(src: &str) -> std::result::Result<Vec<Call>, String> {
+    let mut calls = vec![];
+    for (i, text) in src.lines().enumerate() {
+        if text.trim().is_empty() { continue }
+        let c = parse_line(i+1, text).map_err(|e| format!("line {}: {}", i+1, e))?;
+        calls.push(c);
+    }
+    Ok(calls)
+}
+
+fn parse_line(line: usize, text: &str) -> std::result::Result<Call, String> {
+    let mut toks = Lexer{s: text.as_bytes(), pos: 0};
+    let name = match toks.next()? {
+        Some(Tok::Atom(s)) => s.to_string(),
+        _ => return Err("expected a function name".to_string()),
+    };
+    let mut args = vec![];
+    loop {
+        match toks.next()? {
+            Some(Tok::Arrow) => break,
+            Some(t) => args.push(toks.val(t)?),
+            None => return Err("missing `->`".to_string()),
+        }
+    }
+    let res = match toks.next()? {
+        Some(t) => toks.val(t)?,
+        None => return Err("missing result".to_string()),
+    };
+    if toks.next()?.is_some() { return Err("trailing tokens".to_string()) }
+    Ok(Call{line, name, args, res, text: text.to_string()})
+}
+
+enum Tok<'a> { Atom(&'a str), Str(String), Open, Close, TupOpen, TupClose, Arrow }
+
+struct Lexer<'a> { s: &'a [u8], pos: usize }
+
+impl<'a> Lexer<'a> {
+    fn next(&mut self) -> std::result::Result<Option<Tok<'a>>, String> {
+        while self.pos < self.s.len() && self.s[self.pos] == b' ' { self.pos += 1 }
+        if self.pos >= self.s.len() { return Ok(None) }
+        let start = self.pos;
+        match self.s[start] {
+            b'[' => { self.pos += 1; Ok(Some(Tok::Open)) },
+            b']' => { self.pos += 1; Ok(Some(Tok::Close)) },
+            b'(' if self.s.get(start+1) == Some(&b')') => { self.pos += 2; Ok(Some(Tok::Atom("()"))) },
+            b'(' => { self.pos += 1; Ok(Some(Tok::TupOpen)) },
+            b')' => { self.pos += 1; Ok(Some(Tok::TupClose)) },
+            b'"' => {
+                let mut buf = vec![];
+                self.pos += 1;
+                loop {
+                    match self.s.get(self.pos) {
+                        None => return Err("unterminated string".to_string()),
+                        Some(b'"') => { self.pos += 1; break },
+                        Some(b'\\') => {
+                            match self.s.get(self.pos+1) {
+                                Some(b'n') => buf.push(b'\n'),
+                                Some(&c) => buf.push(c),
+                                None => return Err("unterminated string".to_string()),
+                            }
+                            self.pos += 2;
+                        },
+                        Some(&c) => { buf.push(c); self.pos += 1 },
+                    }
+                }
+                String::from_utf8(buf).map(|s| Some(Tok::Str(s))).map_err(|e| e.to_string())
+            },
+            _ => {
+                while self.pos < self.s.len() && ! b" []()\"".contains(&self.s[self.pos]) {
+                    self.pos += 1
+                }
+                let src: &'a [u8] = self.s;
+                let s = std::str::from_utf8(&src[start .. self.pos]).unwrap();
+                Ok(Some(if s == "->" { Tok::Arrow } else { Tok::Atom(s) }))
+            },
+        }
+    }
+
+    fn val(&mut self, t: Tok<'a>) -> std::result::Result<Val, String> {
+        match t {
+            Tok::Str(s) => Ok(Val::Str(s)),
+            Tok::Open => {
+                let (mut v, mut strs) = (vec![], vec![]);
+                loop {
+                    match self.next()? {
+                        Some(Tok::Close) if strs.is_empty() => return Ok(Val::Ints(v)),
+                        Some(Tok::Close) => return Ok(Val::Strs(strs)),
+                        Some(Tok::Atom(s)) if strs.is_empty() => {
+                            v.push(s.parse().map_err(|_| format!("invalid integer `{}`", s))?)
+                        },
+                        Some(Tok::Str(s)) if v.is_empty() => strs.push(s),
+                        _ => return Err("invalid array".to_string()),
+                    }
+                }
+            },
+            Tok::TupOpen => {
+                let mut v = vec![];
+                loop {
+                    match self.next()? {
+                        Some(Tok::TupClose) => return Ok(Val::Tuple(v)),
+                        Some(t) => v.push(self.val(t)?),
+                        None => return Err("unterminated tuple".to_string()),
+                    }
+                }
+            },
+            Tok::Close | Tok::TupClose | Tok::Arrow => Err("unexpected token".to_string()),
+            Tok::Atom("()") => Ok(Val::Unit),
+            Tok::Atom("true") => Ok(Val::Bool(true)),
+            Tok::Atom("false") => Ok(Val::Bool(false)),
+            Tok::Atom(s) => {
+                let c = s.as_bytes()[0] as char;
+                if let Ok(i) = s.parse::<i64>() {
+                    Ok(Val::Int(i))
+                } else if "csj".contains(c) && s.len() > 1 && s[1..].bytes().all(|b| b.is_ascii_digit()) {
+                    Ok(Val::Handle(c, s[1..].parse().unwrap()))
+                } else if let Ok(f) = s.parse::<f64>() {
+                    Ok(Val::Float(f))
+                } else {
+                    Err(format!("invalid value `{}`", s))
+                }
+            },
+        }
+    }
+}
+
+struct CtxState {
+    ctx: Box<Ctx>,
+    terms: FxHashMap<i64, AST>, // recorded term -> new term
+    app: Option<(AST, Vec<AST>)>, // for `term_app_fun` in older traces
+}
+
+struct SolverState {
+    s: Box<Solver>,
+    lits: FxHashMap<i64, Lit>, // recorded variable -> new positive literal
+}
+
+/// Replay state.
+struct Replay {
+    ctxs: FxHashMap<usize, CtxState>,
+    solvers: FxHashMap<usize, SolverState>,
+    jobs: FxHashMap<usize, bool>, // result of each job
+    n_checks: usize,
+}
+
+// error while replaying one call
+enum Error { Mismatch(String), Invalid(String) }
+
+type Result<T> = std::result::Result<T, Error>;
+
+fn invalid<T>(msg: String) -> Result<T> { Err(Error::Invalid(msg)) }
+
+// the recorded call succeeded, but not this one
+fn failed<T>(e: crate::error::Error) -> Result<T> {
+    Err(Error::Mismatch(format!("call failed: {}", e)))
+}
+
+#[inline]
+fn neg(lit: Lit) -> Lit { Lit::unsafe_from_int(-lit.to_int()) }
+
+impl Replay {
+    fn new() -> Self {
+        Replay{
+            ctxs: FxHashMap::default(), solvers: FxHashMap::default(),
+            jobs: FxHashMap::default(), n_checks: 0,
+        }
+    }
+
+    fn arg<'a>(c: &'a Call, i: usize) -> Result<&'a Val> {
+        match c.args.get(i) {
+            Some(v) => Ok(v),
+            None => invalid(format!("missing argument {}", i)),
+        }
+    }
+
+    fn int(c: &Call, i: usize) -> Result<i64> {
+        match Self::arg(c, i)? { Val::Int(i) => Ok(*i), v => invalid(format!("expected int, got {:?}", v)) }
+    }
+
+    fn bool(c: &Call, i: usize) -> Result<bool> {
+        match Self::arg(c, i)? { Val::Bool(b) => Ok(*b), v => invalid(format!("expected bool, got {:?}", v)) }
+    }
+
+    // options, as the arguments `i ..` of `solver_new` and `solver_set_options`
+    fn options(c: &Call, i: usize) -> Result<Options> {
+        Ok(Options{
+            seed: Self::int(c, i)? as u64,
+            auto_simplify: Self::bool(c, i+1)?,
+            keep_clauses: Self::bool(c, i+2)?,
+        })
+    }
+
+    fn str<'a>(c: &'a Call, i: usize) -> Result<&'a str> {
+        match Self::arg(c, i)? { Val::Str(s) => Ok(s), v => invalid(format!("expected string, got {:?}", v)) }
+    }
+
+    fn handle(c: &Call, i: usize, kind: char) -> Result<usize> {
+        match Self::arg(c, i)? {
+            Val::Handle(k, n) if *k == kind => Ok(*n),
+            v => invalid(format!("expected handle `{}…`, got {:?}", kind, v)),
+        }
+    }
+
+    fn res_handle(c: &Call, kind: char) -> Result<usize> {
+        match &c.res {
+            Val::Handle(k, n) if *k == kind => Ok(*n),
+            v => invalid(format!("expected handle `{}…` as result, got {:?}", kind, v)),
+        }
+    }
+
+    fn res_int(c: &Call) -> Result<i64> {
+        match &c.res { Val::Int(i) => Ok(*i), v => invalid(format!("expected int result, got {:?}", v)) }
+    }
+
+    fn ctx(&mut self, c: &Call, i: usize) -> Result<&mut CtxState> {
+        let h = Self::handle(c, i, 'c')?;
+        match self.ctxs.get_mut(&h) {
+            Some(st) => Ok(st),
+            None => invalid(format!("unknown context c{}", h)),
+        }
+    }
+
+    fn ctx_solver(&mut self, c: &Call, i_s: usize, i_c: usize)
+        -> Result<(&mut SolverState, &mut CtxState)>
+    {
+        let hs = Self::handle(c, i_s, 's')?;
+        let hc = Self::handle(c, i_c, 'c')?;
+        match (self.solvers.get_mut(&hs), self.ctxs.get_mut(&hc)) {
+            (Some(s), Some(ctx)) => Ok((s, ctx)),
+            (None, _) => invalid(format!("unknown solver s{}", hs)),
+            (_, None) => invalid(format!("unknown context c{}", hc)),
+        }
+    }
+
+    fn solver(&mut self, c: &Call, i: usize) -> Result<&mut SolverState> {
+        let h = Self::handle(c, i, 's')?;
+        match self.solvers.get_mut(&h) {
+            Some(st) => Ok(st),
+            None => invalid(format!("unknown solver s{}", h)),
+        }
+    }
+
+    fn check_bool(&mut self, what: &str, expected: &Val, got: bool) -> Result<()> {
+        self.n_checks += 1;
+        match expected {
+            Val::Bool(b) if *b == got => Ok(()),
+            Val::Bool(b) => Err(Error::Mismatch(format!("{}: expected {}, got {}", what, b, got))),
+            v => invalid(format!("expected bool result, got {:?}", v)),
+        }
+    }
+
+    fn run(&mut self, c: &Call) -> Result<()> {
+        match c.name.as_str() {
+            "ctx_new" => {
+                let h = Self::res_handle(c, 'c')?;
+                self.ctxs.insert(h, CtxState{
+                    ctx: Box::new(Ctx::new()), terms: FxHashMap::default(), app: None,
+                });
+            },
+            "ctx_compact" => {
+                let h = Self::handle(c, 1, 'c')?;
+                let (roots, rs) = match (Self::arg(c, 2)?, &c.res) {
+                    (Val::Ints(ts), Val::Ints(rs)) if ts.len() == rs.len() => (ts, rs),
+                    (v, r) => return invalid(format!("expected arrays, got {:?} -> {:?}", v, r)),
+                };
+                let st = self.ctx(c, 0)?;
+                let roots = roots.iter().map(|u| st.term(*u)).collect::<Result<Vec<_>>>()?;
+                let (ctx, ts) = st.ctx.api_compact(&roots);
+                let terms = rs.iter().cloned().zip(ts.iter().cloned()).collect();
+                self.ctxs.insert(h, CtxState{ctx: Box::new(ctx), terms, app: None});
+            },
+            "ctx_delete" => {
+                let h = Self::handle(c, 0, 'c')?;
+                self.ctxs.remove(&h);
+            },
+            "solver_new" => {
+                let opts = Self::options(c, 1)?;
+                let h = Self::res_handle(c, 's')?;
+                let st = self.ctx(c, 0)?;
+                let s = Box::new(Solver::new_with(&mut st.ctx, opts));
+                self.solvers.insert(h, SolverState{s, lits: FxHashMap::default()});
+            },
+            "solver_delete" => {
+                let h = Self::handle(c, 0, 's')?;
+                self.solvers.remove(&h);
+            },
+            "solver_set_options" => {
+                let opts = Self::options(c, 1)?;
+                self.solver(c, 0)?.s.api_set_options(opts);
+            },
+            "solver_new_lit" => {
+                let r = Self::res_int(c)?;
+                let st = self.solver(c, 0)?;
+                let lit = st.s.api_make_lit();
+                st.add_lit(r, lit);
+            },
+            "solver_new_term_lit" => {
+                let r = Self::res_int(c)?;
+                let (st, ctx) = self.ctx_solver(c, 0, 1)?;
+                let t = ctx.term(Self::int(c, 2)?)?;
+                let lit = st.s.api_make_term_lit(&mut ctx.ctx, t);
+                st.add_lit(r, lit);
+            },
+            "solver_new_term_lits" => {
+                let (ts, rs) = match (Self::arg(c, 2)?, &c.res) {
+                    (Val::Ints(ts), Val::Ints(rs)) if ts.len() == rs.len() => (ts, rs),
+                    (v, r) => return invalid(format!("expected arrays, got {:?} -> {:?}", v, r)),
+                };
+                let (st, ctx) = self.ctx_solver(c, 0, 1)?;
+                let ts = ts.iter().map(|u| ctx.term(*u)).collect::<Result<Vec<_>>>()?;
+                let lits = st.s.api_make_term_lits(&mut ctx.ctx, &ts);
+                for (&r, &lit) in rs.iter().zip(lits.iter()) { st.add_lit(r, lit) }
+            },
+            "solver_add_clause_lit" => {
+                let st = self.solver(c, 0)?;
+                let lit = st.lit(Self::int(c, 1)?)?;
+                st.s.api_add_clause_lit(lit);
+            },
+            "solver_add_clause" => self.solver(c, 0)?.s.api_add_clause(),
+            "solver_add_clause_a" | "solver_add_clauses" => {
+                let lits = match Self::arg(c, 1)? {
+                    Val::Ints(v) => v,
+                    v => return invalid(format!("expected array, got {:?}", v)),
+                };
+                let st = self.solver(c, 0)?;
+                let lits =
+                    lits.iter()
+                    .map(|&l| if l == 0 { Ok(0) } else { st.lit(l).map(|l| l.to_int()) })
+                    .collect::<Result<Vec<i32>>>()?;
+                if c.name == "solver_add_clauses" {
+                    st.s.api_add_clauses_zero_sep(&lits).or_else(failed)?;
+                } else {
+                    let lits: Vec<Lit> = lits.iter().map(|&l| Lit::unsafe_from_int(l)).collect();
+                    st.s.api_add_clause_from(&lits).or_else(failed)?;
+                }
+            },
+            "solver_add_assumption" => {
+                let st = self.solver(c, 0)?;
+                let lit = st.lit(Self::int(c, 1)?)?;
+                st.s.api_add_assumption(lit);
+            },
+            "solver_solve" => {
+                let (st, ctx) = self.ctx_solver(c, 0, 1)?;
+                let r = st.s.api_solve(&mut ctx.ctx);
+                self.check_bool(&c.name, &c.res, r)?;
+            },
+            "solver_solve_portfolio" => {
+                let seed = match Self::arg(c, 2)? {
+                    Val::Ints(v) if v.len() > 0 => v[0] as u64,
+                    v => return invalid(format!("expected non-empty array, got {:?}", v)),
+                };
+                let (st, ctx) = self.ctx_solver(c, 0, 1)?;
+                st.s.clauses().or_else(failed)?;
+                // like the stub, solve a copy: the solver only loses its assumptions.
+                // Every worker gives the same answer, the first one is enough.
+                let assumptions = st.s.api_take_assumptions();
+                let p = portfolio::Problem::new(&st.s, &ctx.ctx, &assumptions).or_else(failed)?;
+                let (r, _) = p.solve_as_worker(0, seed);
+                self.check_bool(&c.name, &c.res, r)?;
+            },
+            "solver_solve_async" => {
+                let h = Self::res_handle(c, 'j')?;
+                let (st, ctx) = self.ctx_solver(c, 0, 1)?;
+                let r = st.s.api_solve(&mut ctx.ctx);
+                self.jobs.insert(h, r);
+            },
+            "job_poll" | "job_wait" => {
+                let h = Self::handle(c, 0, 'j')?;
+                let r = match self.jobs.get(&h) {
+                    Some(r) => *r,
+                    None => return invalid(format!("unknown job j{}", h)),
+                };
+                // only finished jobs can be checked
+                match Self::res_int(c)? {
+                    1 => self.check_bool(&c.name, &Val::Bool(true), r)?,
+                    2 => self.check_bool(&c.name, &Val::Bool(false), r)?,
+                    _ => (),
+                }
+            },
+            "solver_simplify" => { self.solver(c, 0)?.s.api_simplify(); },
+            "solver_load_dimacs" => {
+                // the trace holds the contents of the file
+                let src = Self::str(c, 1)?.to_string();
+                let recorded = match &c.res {
+                    Val::Ints(v) => v.clone(),
+                    v => return invalid(format!("expected array result, got {:?}", v)),
+                };
+                let st = self.solver(c, 0)?;
+                let vars = match dimacs::load(&mut st.s, &src) {
+                    Ok(v) => v,
+                    Err(e) => return invalid(e),
+                };
+                for (r, lit) in recorded.iter().zip(vars.iter()) { st.add_lit(*r, *lit) }
+            },
+            "ty_bool" => {
+                let r = Self::res_int(c)?;
+                let st = self.ctx(c, 0)?;
+                let t = st.ctx.api_ty_bool();
+                st.terms.insert(r, t);
+            },
+            "ty_const" => {
+                let r = Self::res_int(c)?;
+                let name = Self::str(c, 1)?;
+                let st = self.ctx(c, 0)?;
+                let t = st.ctx.api_ty_const(name).or_else(failed)?;
+                st.terms.insert(r, t);
+            },
+            "term_bool" => {
+                let r = Self::res_int(c)?;
+                let b = Self::bool(c, 1)?;
+                let st = self.ctx(c, 0)?;
+                let t = st.ctx.api_bool(b);
+                st.terms.insert(r, t);
+            },
+            "term_not" => {
+                let r = Self::res_int(c)?;
+                let st = self.ctx(c, 0)?;
+                let t = st.term(Self::int(c, 1)?)?;
+                let u = st.ctx.api_not(t);
+                st.terms.insert(r, u);
+            },
+            "term_const" => {
+                let r = Self::res_int(c)?;
+                let name = Self::str(c, 1)?;
+                let args = match Self::arg(c, 2)? {
+                    Val::Ints(v) => v,
+                    v => return invalid(format!("expected array, got {:?}", v)),
+                };
+                let st = self.ctx(c, 0)?;
+                let args = args.iter().map(|u| st.term(*u)).collect::<Result<Vec<_>>>()?;
+                let ret = st.term(Self::int(c, 3)?)?;
+                let t = st.ctx.api_const(name, &args, ret).or_else(failed)?;
+                st.terms.insert(r, t);
+            },
+            "term_cstor" => {
+                let r = Self::res_int(c)?;
+                let name = Self::str(c, 1)?;
+                let args = match Self::arg(c, 2)? {
+                    Val::Ints(v) => v,
+                    v => return invalid(format!("expected array, got {:?}", v)),
+                };
+                let st = self.ctx(c, 0)?;
+                let args = args.iter().map(|u| st.term(*u)).collect::<Result<Vec<_>>>()?;
+                let ret = st.term(Self::int(c, 3)?)?;
+                let t = st.ctx.api_cstor(name, &args, ret).or_else(failed)?;
+                st.terms.insert(r, t);
+            },
+            // recorded by older versions
+            "term_set_cstor" => {
+                let st = self.ctx(c, 0)?;
+                let t = st.term(Self::int(c, 1)?)?;
+                st.ctx.api_set_is_cstor(t).or_else(failed)?;
+            },
+            "term_eq" => {
+                let r = Self::res_int(c)?;
+                let st = self.ctx(c, 0)?;
+                let t1 = st.term(Self::int(c, 1)?)?;
+                let t2 = st.term(Self::int(c, 2)?)?;
+                let t = st.ctx.api_eq(t1, t2).or_else(failed)?;
+                st.terms.insert(r, t);
+            },
+            "term_select" => {
+                let r = Self::res_int(c)?;
+                let idx = Self::int(c, 2)? as u32;
+                let st = self.ctx(c, 0)?;
+                let f = st.term(Self::int(c, 1)?)?;
+                let u = st.term(Self::int(c, 3)?)?;
+                let t = st.ctx.api_select(f, idx, u).or_else(failed)?;
+                st.terms.insert(r, t);
+            },
+            "term_import" => {
+                let names: Vec<&str> = match Self::arg(c, 1)? {
+                    Val::Strs(v) => v.iter().map(|s| s.as_str()).collect(),
+                    Val::Ints(v) if v.is_empty() => vec!(),
+                    v => return invalid(format!("expected array of strings, got {:?}", v)),
+                };
+                let nodes: Vec<i32> = match Self::arg(c, 2)? {
+                    Val::Ints(v) => v.iter().map(|&x| x as i32).collect(),
+                    v => return invalid(format!("expected array, got {:?}", v)),
+                };
+                let recorded = match &c.res {
+                    Val::Ints(v) => v,
+                    v => return invalid(format!("expected array result, got {:?}", v)),
+                };
+                let st = self.ctx(c, 0)?;
+                let ts = st.ctx.api_import(&names, &nodes).or_else(failed)?;
+                if ts.len() != recorded.len() {
+                    return invalid(format!("{} nodes, {} recorded", ts.len(), recorded.len()))
+                }
+                for (&r, &t) in recorded.iter().zip(ts.iter()) { st.terms.insert(r, t); }
+            },
+            "term_app" => {
+                let r = Self::res_int(c)?;
+                let args = match Self::arg(c, 2)? {
+                    Val::Ints(v) => v,
+                    v => return invalid(format!("expected array, got {:?}", v)),
+                };
+                let st = self.ctx(c, 0)?;
+                let f = st.term(Self::int(c, 1)?)?;
+                let args = args.iter().map(|u| st.term(*u)).collect::<Result<Vec<_>>>()?;
+                let t = st.ctx.api_app(f, &args).or_else(failed)?;
+                st.terms.insert(r, t);
+            },
+            // the builder used before `term_app`
+            "term_app_fun" => {
+                let st = self.ctx(c, 0)?;
+                let f = st.term(Self::int(c, 1)?)?;
+                st.app = Some((f, vec!()));
+            },
+            "term_app_arg" => {
+                let st = self.ctx(c, 0)?;
+                let t = st.term(Self::int(c, 1)?)?;
+                match &mut st.app {
+                    Some((_, args)) => args.push(t),
+                    None => return invalid("term_app_arg without term_app_fun".to_string()),
+                }
+            },
+            "term_app_finalize" => {
+                let r = Self::res_int(c)?;
+                let st = self.ctx(c, 0)?;
+                let (f, args) = match st.app.take() {
+                    Some(app) => app,
+                    None => return invalid("term_app_finalize without term_app_fun".to_string()),
+                };
+                let t = st.ctx.api_app(f, &args).or_else(failed)?;
+                st.terms.insert(r, t);
+            },
+            "smtlib_parse_and_run" => {
+                let src = Self::str(c, 1)?;
+                let st = self.ctx(c, 0)?;
+                let out = smtlib::parse_and_run(&mut st.ctx, src);
+                self.n_checks += 1;
+                match &c.res {
+                    Val::Str(s) if *s == out => (),
+                    Val::Str(s) => {
+                        return Err(Error::Mismatch(format!("expected output {:?}, got {:?}", s, out)))
+                    },
+                    v => return invalid(format!("expected string result, got {:?}", v)),
+                }
+            },
+            "solver_new_proved_lvl_0" => { self.solver(c, 0)?.s.api_new_proved_at_lvl_0(); },
+            // queries returning terms: map the recorded terms to the new ones.
+            // `term_get_app_fun` and `term_get_app_nth_arg` are only found
+            // in older traces.
+            "term_get_app_fun" => {
+                let r = Self::res_int(c)?;
+                let st = self.ctx(c, 0)?;
+                let t = st.term(Self::int(c, 1)?)?;
+                let f = st.ctx.api_app_get_fun(t).or_else(failed)?;
+                st.terms.insert(r, f);
+            },
+            "term_get_app_nth_arg" => {
+                let r = Self::res_int(c)?;
+                let i = Self::int(c, 2)? as usize;
+                let st = self.ctx(c, 0)?;
+                let t = st.term(Self::int(c, 1)?)?;
+                let a = match st.ctx.api_app_get_args(t).or_else(failed)?.get(i) {
+                    Some(&a) => a,
+                    None => return Err(Error::Mismatch(format!("no argument {}", i))),
+                };
+                st.terms.insert(r, a);
+            },
+            "term_view" => {
+                let (rk, rhead, rargs, rname) = match &c.res {
+                    Val::Tuple(v) => match &v[..] {
+                        [Val::Int(k), Val::Int(h), Val::Ints(args), Val::Str(name)] => (*k, *h, args, name),
+                        _ => return invalid(format!("expected (kind head args name), got {:?}", v)),
+                    },
+                    v => return invalid(format!("expected tuple result, got {:?}", v)),
+                };
+                let st = self.ctx(c, 0)?;
+                let t = st.term(Self::int(c, 1)?)?;
+                let k = st.ctx.api_kind(t);
+                let mismatch = if k as u8 as i64 != rk {
+                    Some(format!("kind {}, recorded {}", k as u8, rk))
+                } else {
+                    match k {
+                        AstKind::Bool => {
+                            let b = st.ctx.api_get_bool(t).or_else(failed)? as i64;
+                            if b != rhead { Some(format!("bool {}, recorded {}", b, rhead)) } else { None }
+                        },
+                        AstKind::Const | AstKind::Cstor => {
+                            let name = st.ctx.api_const_get_name(t).or_else(failed)?;
+                            if name != rname.as_str() { Some(format!("name {:?}, recorded {:?}", name, rname)) } else { None }
+                        },
+                        AstKind::App | AstKind::Not | AstKind::Selector => {
+                            let f = st.ctx.api_app_get_fun(t).or_else(failed)?;
+                            let args = st.ctx.api_app_get_args(t).or_else(failed)?.to_vec();
+                            if args.len() != rargs.len() {
+                                Some(format!("{} args, recorded {}", args.len(), rargs.len()))
+                            } else {
+                                st.terms.insert(rhead, f);
+                                for (&r, &u) in rargs.iter().zip(args.iter()) { st.terms.insert(r, u); }
+                                None
+                            }
+                        },
+                    }
+                };
+                self.n_checks += 1;
+                if let Some(msg) = mismatch {
+                    return Err(Error::Mismatch(format!("{}: {}", c.name, msg)))
+                }
+            },
+            "term_subterms" => {
+                let recorded = match &c.res {
+                    Val::Ints(v) => v,
+                    v => return invalid(format!("expected array result, got {:?}", v)),
+                };
+                let st = self.ctx(c, 0)?;
+                let t = st.term(Self::int(c, 1)?)?;
+                let sub = st.ctx.api_subterms(t);
+                if sub.len() != recorded.len() {
+                    return Err(Error::Mismatch(
+                        format!("{} subterms, {} recorded", sub.len(), recorded.len())))
+                }
+                for (&r, &u) in recorded.iter().zip(sub.iter()) { st.terms.insert(r, u); }
+            },
+            // queries and printers do not change the state
+            "job_detach" | "solver_unsat_core" | "solver_unsat_core_contains"
+                | "nclauses" | "nlits" | "ndecisions" | "nprops" | "nconflicts" | "solver_stats"
+                | "solver_value_lvl_0" | "solver_value" | "solver_values" | "solver_model_bits"
+                | "solver_n_proved_lvl_0" | "solver_proved_lvl_0_all"
+                | "solver_proved_lvl_0" | "term_kind" | "term_get_bool"
+                | "term_get_const_name" | "term_get_app_n_args" | "term_to_smt2"
+                | "ctx_decls_to_smt2" | "ctx_stats" | "peak_mem" | "ctx_dump" | "solver_to_smt2"
+                | "solver_to_dimacs" | "set_log_lvl" => (),
+            name => return invalid(format!("unknown function `{}`", name)),
+        }
+        Ok(())
+    }
+}
+
+impl CtxState {
+    fn term(&self, t: i64) -> Result<AST> {
+        match self.terms.get(&t) {
+            Some(u) => Ok(*u),
+            None => invalid(format!("unknown term {}", t)),
+        }
+    }
+}
+
+impl SolverState {
+    // `lit` is the new literal for the recorded literal `r`
+    fn add_lit(&mut self, r: i64, lit: Lit) {
+        self.lits.insert(r.abs(), if r > 0 { lit } else { neg(lit) });
+    }
+
+    fn lit(&self, r: i64) -> Result<Lit> {
+        match self.lits.get(&r.abs()) {
+            Some(&lit) => Ok(if r > 0 { lit } else { neg(lit) }),
+            None => invalid(format!("unknown literal {}", r)),
+        }
+    }
+}
+
+/// Print `calls` back as a trace.
+pub fn write(calls: &[Call], out: &mut String) {
+    for c in calls.iter() {
+        out.push_str(&c.text);
+        out.push('\n');
+    }
+}
+
+/// Replay `calls` in order, stopping at the first failure.
+pub fn replay(calls: &[Call]) -> Outcome {
+    let mut r = Replay::new();
+    for c in calls.iter() {
+        match panic::catch_unwind(AssertUnwindSafe(|| r.run(c))) {
+            Ok(Ok(())) => (),
+            Ok(Err(Error::Mismatch(msg))) => return Outcome::Mismatch{line: c.line, msg},
+            Ok(Err(Error::Invalid(msg))) => return Outcome::Invalid{line: c.line, msg},
+            Err(e) => {
+                let msg =
+                    e.downcast_ref::<&str>().map(|s| s.to_string())
+                    .or_else(|| e.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "panic".to_string());
+                return Outcome::Crash{line: c.line, msg}
+            },
+        }
+    }
+    Outcome::Ok{n_checks: r.n_checks}
+}
+
+/// Shrink a failing trace.
+///
+/// The trace is first cut after the failing call, then calls are removed
+/// (delta debugging) as long as the replay still fails on the same call,
+/// in the same way. Returns `None` if `calls` does not fail.
+///
+/// The replays are expected to panic, so the panic hook is replaced by
+/// a silent one meanwhile. The hook is global: panics in other threads
+/// are silenced too until the previous hook is restored, on return.
+pub fn minimize(calls: &[Call]) -> Option<Vec<Call>> {
+    let prev = panic::take_hook();
+    panic::set_hook(Box::new(|_| ()));
+    let res = shrink(calls);
+    panic::set_hook(prev);
+    res
+}
+
+fn shrink(calls: &[Call]) -> Option<Vec<Call>> {
+    let out = replay(calls);
+    if ! out.is_failure() { return None }
+    let line = out.line().unwrap();
+    let same_failure = |cs: &[Call]| {
+        let o = replay(cs);
+        match (&o, &out) {
+            (Outcome::Mismatch{line: l, msg: m}, Outcome::Mismatch{msg: m0, ..}) |
+            (Outcome::Crash{line: l, msg: m}, Outcome::Crash{msg: m0, ..}) => {
+                *l == line && m == m0
+            },
+            _ => false,
+        }
+    };
+
+    // shortest failing prefix
+    let mut cur: Vec<Call> = calls.iter().take_while(|c| c.line <= line).cloned().collect();
+
+    // remove chunks of calls, except the last one, with decreasing size
+    let mut n_chunks = 2;
+    while cur.len() > 1 {
+        let len = cur.len() - 1;
+        let chunk = (len + n_chunks - 1) / n_chunks;
+        let mut removed = false;
+        let mut start = 0;
+        while start < cur.len() - 1 {
+            let stop = (start + chunk).min(cur.len() - 1);
+            let cand: Vec<Call> =
+                cur[..start].iter().chain(cur[stop..].iter()).cloned().collect();
+            if same_failure(&cand) {
+                cur = cand;
+                removed = true;
+            } else {
+                start = stop;
+            }
+        }
+        if removed {
+            n_chunks = 2.max(n_chunks - 1);
+        } else if chunk == 1 {
+            break
+        } else {
+            n_chunks = (2 * n_chunks).min(cur.len() - 1);
+        }
+    }
+    Some(cur)
+}
@@ -0,0 +1,1408 @@
+//! SMT-LIB 2.6 front-end.
+//!
+//! Parses scripts in the fragment `Ctx` supports (uninterpreted sorts
+//! and functions, equality, boolean connectives, datatype constructors)
+//! and runs them against a fresh `Solver`.
+//!
+//! Boolean connectives are not terms in `Ctx`, so they are turned into
+//! clauses on the fly (Tseitin encoding). `push`/`pop` are implemented
+//! with one activation literal per scope.
+//!
+//! The congruence closure only knows that constructors are injective and
+//! distinct. Each term of a datatype whose constructors are all constants
+//! gets a clause saying it is one of them. For other datatypes, a `sat`
+//! whose model has a cyclic value or a value that is no constructor term
+//! is reported as `unknown`.
+
+use {
+    std::{fmt::Write, mem},
+    fxhash::{FxHashMap, FxHashSet},
+    batsmt_core::{AstView, Manager, ast_u32::AST},
+    crate::{
+        ctx::Ctx,
+        solver::{Solver, Lit, Lbool},
+        printer,
+    },
+};
+
+type Result<T> = std::result::Result<T, String>;
+
+/// Parse `src` and run its commands, returning the solver's output.
+///
+/// Processing stops at the first error, which is reported in the output
+/// as `(error "...")`.
+pub fn parse_and_run(ctx: &mut Ctx, src: &str) -> String {
+    let mut d = Driver::new(ctx);
+    let mut p = Parser::new(src);
+    loop {
+        let r = p.next().and_then(|cmd| match cmd {
+            None => Ok(false),
+            Some(cmd) => d.run(&cmd),
+        });
+        match r {
+            Ok(true) => (),
+            Ok(false) => break,
+            Err(e) => {
+                let _ = writeln!(d.out, "(error \"{}\")", e.replace('"', "\"\""));
+                break
+            },
+        }
+    }
+    d.out
+}
+
+/// S-expressions.
+enum Sexp {
+    Atom(String),
+    Str(String),
+    List(Vec<Sexp>),
+}
+
+// dropping deeply nested lists recursively could overflow the stack
+impl Drop for Sexp {
+    fn drop(&mut self) {
+        if let Sexp::List(l) = self {
+            let mut todo = mem::replace(l, vec![]);
+            while let Some(mut e) = todo.pop() {
+                if let Sexp::List(l) = &mut e { todo.extend(l.drain(..)) }
+            }
+        }
+    }
+}
+
+// names come from the script, and the parser rejects those that
+// `printer::pp_symbol` cannot print
+fn pp_symbol(out: &mut String, s: &str) {
+    printer::pp_symbol(out, s).expect("invalid symbol")
+}
+
+/// Short description of `e`, for error messages.
+fn describe(e: &Sexp) -> String {
+    match e {
+        Sexp::Atom(s) => format!("`{}`", s),
+        Sexp::Str(s) => format!("string \"{}\"", s),
+        Sexp::List(_) => "a list".to_string(),
+    }
+}
+
+mod parser {
+    use super::*;
+
+    /// Incremental S-expression parser.
+    pub(super) struct Parser<'a> {
+        src: &'a [u8],
+        pos: usize,
+    }
+
+    impl<'a> Parser<'a> {
+        pub fn new(src: &'a str) -> Self { Parser{src: src.as_bytes(), pos: 0} }
+
+        fn skip_ws(&mut self) {
+            while self.pos < self.src.len() {
+                match self.src[self.pos] {
+                    b';' => {
+                        while self.pos < self.src.len() && self.src[self.pos] != b'\n' {
+                            self.pos += 1
+                        }
+                    },
+                    c if c.is_ascii_whitespace() => self.pos += 1,
+                    _ => break,
+                }
+            }
+        }
+
+        fn slice(&self, i: usize, j: usize) -> String {
+            String::from_utf8_lossy(&self.src[i..j]).into_owned()
+        }
+
+        /// Next toplevel S-expression, if any.
+        pub fn next(&mut self) -> Result<Option<Sexp>> {
+            self.skip_ws();
+            if self.pos >= self.src.len() { Ok(None) } else { self.sexp().map(Some) }
+        }
+
+        fn sexp(&mut self) -> Result<Sexp> {
+            // lists being parsed, innermost last; no recursion, as
+            // expressions can be nested arbitrarily deep
+            let mut open: Vec<Vec<Sexp>> = vec![];
+            loop {
+                self.skip_ws();
+                if self.pos >= self.src.len() {
+                    return Err(if open.is_empty() { "unexpected end of input" }
+                        else { "unclosed parenthesis" }.to_string())
+                }
+                let e = match self.src[self.pos] {
+                    b'(' => {
+                        self.pos += 1;
+                        open.push(vec![]);
+                        continue
+                    },
+                    b')' => match open.pop() {
+                        None => return Err("unexpected ')'".to_string()),
+                        Some(l) => {
+                            self.pos += 1;
+                            Sexp::List(l)
+                        },
+                    },
+                    _ => self.leaf()?,
+                };
+                match open.last_mut() {
+                    None => return Ok(e),
+                    Some(l) => l.push(e),
+                }
+            }
+        }
+
+        // a symbol or a string
+        fn leaf(&mut self) -> Result<Sexp> {
+            match self.src[self.pos] {
+                b'|' => {
+                    let start = self.pos + 1;
+                    match self.src[start..].iter().position(|&c| c == b'|') {
+                        None => Err("unclosed quoted symbol".to_string()),
+                        Some(n) if self.src[start..start+n].contains(&b'\\') => {
+                            Err("quoted symbols cannot contain `\\`".to_string())
+                        },
+                        Some(n) => {
+                            self.pos = start + n + 1;
+                            Ok(Sexp::Atom(self.slice(start, start+n)))
+                        },
+                    }
+                },
+                b'"' => {
+                    let mut s = String::new();
+                    self.pos += 1;
+                    loop {
+                        let start = self.pos;
+                        match self.src[start..].iter().position(|&c| c == b'"') {
+                            None => return Err("unclosed string".to_string()),
+                            Some(n) => {
+                                s.push_str(&self.slice(start, start+n));
+                                self.pos = start + n + 1;
+                            },
+                        }
+                        // `""` is an escaped quote
+                        if self.pos < self.src.len() && self.src[self.pos] == b'"' {
+                            s.push('"');
+                            self.pos += 1;
+                        } else {
+                            return Ok(Sexp::Str(s))
+                        }
+                    }
+                },
+                _ => {
+                    let start = self.pos;
+                    while self.pos < self.src.len() {
+                        match self.src[self.pos] {
+                            b'(' | b')' | b'"' | b'|' | b';' => break,
+                            c if c.is_ascii_whitespace() => break,
+                            _ => self.pos += 1,
+                        }
+                    }
+                    Ok(Sexp::Atom(self.slice(start, self.pos)))
+                },
+            }
+        }
+    }
+}
+
+use parser::Parser;
+
+/// A declared symbol.
+#[derive(Clone,Debug)]
+enum Decl {
+    Sort(AST),
+    Fun {
+        f: AST,
+        args: Vec<AST>,
+        ret: AST,
+    },
+    Selector, // declared by a datatype, but cannot be used
+    Def(Val), // defined by `define-fun`, without arguments
+}
+
+/// The value of an expression.
+#[derive(Copy,Clone,Debug)]
+enum Val {
+    Term(AST), // a term of the context
+    Lit(Lit), // a boolean formula, already turned into clauses
+}
+
+struct Scope {
+    act: Lit, // activation literal
+    decls: Vec<(String, Option<Decl>)>, // previous declarations, to restore
+    n_consts: usize,
+    n_named: usize,
+}
+
+struct Driver<'a> {
+    ctx: &'a mut Ctx,
+    s: Solver,
+    out: String,
+    decls: FxHashMap<String, Decl>,
+    consts: Vec<(String, AST)>, // declared constants, in order
+    named: Vec<(String, Lit)>, // named assertions
+    scopes: Vec<Scope>,
+    term_lits: FxHashMap<AST, Lit>,
+    lit_terms: FxHashMap<i32, AST>, // boolean formulas turned into terms
+    lit_true: Option<Lit>,
+    last_res: Option<bool>,
+    n_fresh: usize,
+    enums: FxHashMap<AST, Vec<AST>>, // datatypes with only constant constructors
+    datatypes: FxHashSet<AST>, // the other datatypes
+    covered: FxHashSet<AST>, // terms of an enum with their clause
+}
+
+#[inline]
+fn neg(lit: Lit) -> Lit { Lit::unsafe_from_int(-lit.to_int()) }
+
+fn atom(e: &Sexp) -> Result<&str> {
+    match e {
+        Sexp::Atom(s) => Ok(s),
+        _ => Err(format!("expected a symbol, got {}", describe(e))),
+    }
+}
+
+// a `let` binding `(x e)`
+fn binding(b: &Sexp) -> Result<(&str, &Sexp)> {
+    match b {
+        Sexp::List(b) if b.len() == 2 => Ok((atom(&b[0])?, &b[1])),
+        _ => Err("invalid let binding".to_string()),
+    }
+}
+
+/// Expressions whose arguments are being evaluated, see `Driver::eval`.
+enum Frame<'e> {
+    /// Application of `head`, with the values of the first arguments.
+    App { head: &'e str, args: &'e [Sexp], vals: Vec<Val> },
+    /// `let`, with the values of the first bindings.
+    Let { bs: &'e [Sexp], body: &'e Sexp, vals: Vec<(String, Val)> },
+    /// Body of a `let`, whose bindings start at `env[n_env]`.
+    LetBody { n_env: usize },
+}
+
+impl<'a> Driver<'a> {
+    fn new(ctx: &'a mut Ctx) -> Self {
+        let s = Solver::new(ctx);
+        Driver{
+            ctx, s, out: String::new(), decls: FxHashMap::default(),
+            consts: vec![], named: vec![], scopes: vec![],
+            term_lits: FxHashMap::default(), lit_terms: FxHashMap::default(),
+            lit_true: None, last_res: None, n_fresh: 0,
+            enums: FxHashMap::default(), datatypes: FxHashSet::default(),
+            covered: FxHashSet::default(),
+        }
+    }
+
+    /// Run one command. Returns `false` on `exit`.
+    fn run(&mut self, cmd: &Sexp) -> Result<bool> {
+        let l = match cmd {
+            Sexp::List(l) if l.len() > 0 => l,
+            _ => return Err(format!("expected a command, got {}", describe(cmd))),
+        };
+        let args = &l[1..];
+        match atom(&l[0])? {
+            "set-logic" | "set-info" | "set-option" => (),
+            "exit" => return Ok(false),
+            "echo" => match args {
+                [Sexp::Str(s)] => { let _ = writeln!(self.out, "\"{}\"", s); },
+                _ => return Err("echo expects a string".to_string()),
+            },
+            "declare-sort" => match args {
+                [name] | [name, Sexp::Atom(_)] => {
+                    if let [_, Sexp::Atom(n)] = args {
+                        if n != "0" { return Err("parametric sorts are not supported".to_string()) }
+                    }
+                    self.declare_sort(atom(name)?)?;
+                },
+                _ => return Err("invalid declare-sort".to_string()),
+            },
+            "declare-const" => match args {
+                [name, ret] => {
+                    let ret = self.sort(ret)?;
+                    self.declare_fun(atom(name)?, vec![], ret)?;
+                },
+                _ => return Err("invalid declare-const".to_string()),
+            },
+            "declare-fun" => match args {
+                [name, Sexp::List(sorts), ret] => {
+                    let args = sorts.iter().map(|s| self.sort(s)).collect::<Result<Vec<_>>>()?;
+                    let ret = self.sort(ret)?;
+                    self.declare_fun(atom(name)?, args, ret)?;
+                },
+                _ => return Err("invalid declare-fun".to_string()),
+            },
+            "define-fun" => match args {
+                [name, Sexp::List(params), ret, body] if params.len() == 0 => {
+                    let ret = self.sort(ret)?;
+                    let v = self.eval(body, &mut vec![])?;
+                    if self.sort_of(v) != ret {
+                        return Err(format!("ill-sorted definition of `{}`", atom(name)?))
+                    }
+                    self.declare(atom(name)?, Decl::Def(v));
+                },
+                [_, Sexp::List(_), _, _] => {
+                    return Err("define-fun with parameters is not supported".to_string())
+                },
+                _ => return Err("invalid define-fun".to_string()),
+            },
+            "declare-datatypes" => match args {
+                [Sexp::List(sorts), Sexp::List(cstors)] if sorts.len() == cstors.len() => {
+                    self.declare_datatypes(sorts, cstors)?;
+                },
+                _ => return Err("invalid declare-datatypes".to_string()),
+            },
+            "assert" => match args {
+                [e] => self.assert(e)?,
+                _ => return Err("invalid assert".to_string()),
+            },
+            "check-sat" => self.check_sat(&[])?,
+            "check-sat-assuming" => match args {
+                [Sexp::List(l)] => {
+                    let mut lits = vec![];
+                    for e in l.iter() {
+                        let v = self.eval(e, &mut vec![])?;
+                        lits.push(self.to_lit(v)?);
+                    }
+                    self.check_sat(&lits)?
+                },
+                _ => return Err("invalid check-sat-assuming".to_string()),
+            },
+            "push" => {
+                for _ in 0 .. self.n_scopes(args)? { self.push() }
+            },
+            "pop" => {
+                let n = self.n_scopes(args)?;
+                if n > self.scopes.len() {
+                    return Err(format!("cannot pop {} scopes", n))
+                }
+                for _ in 0 .. n { self.pop() }
+            },
+            "get-model" => self.get_model()?,
+            "get-unsat-core" => self.get_unsat_core()?,
+            s => return Err(format!("unsupported command `{}`", s)),
+        }
+        Ok(true)
+    }
+
+    fn n_scopes(&self, args: &[Sexp]) -> Result<usize> {
+        match args {
+            [] => Ok(1),
+            [Sexp::Atom(n)] => n.parse().map_err(|_| format!("invalid number `{}`", n)),
+            _ => Err("expected a number of scopes".to_string()),
+        }
+    }
+
+    fn declare(&mut self, name: &str, d: Decl) {
+        let old = self.decls.insert(name.to_string(), d);
+        if let Some(sc) = self.scopes.last_mut() {
+            sc.decls.push((name.to_string(), old));
+        }
+    }
+
+    fn declare_sort(&mut self, name: &str) -> Result<AST> {
+        if let Some(Decl::Sort(_)) = self.decls.get(name) {
+            return Err(format!("`{}` is already declared", name))
+        }
+        // `Ctx` shares symbols by name
+        let ty = self.ctx.api_ty_const(name)
+            .map_err(|_| format!("`{}` is already a function", name))?;
+        self.declare(name, Decl::Sort(ty));
+        Ok(ty)
+    }
+
+    fn declare_fun(&mut self, name: &str, args: Vec<AST>, ret: AST) -> Result<AST> {
+        // `Ctx` shares symbols by name
+        let f = self.ctx.api_const(name, &args, ret)
+            .map_err(|_| format!("`{}` is already declared with another sort", name))?;
+        Ok(self.declare_sym(name, f, args, ret))
+    }
+
+    fn declare_cstor(&mut self, name: &str, args: Vec<AST>, ret: AST) -> Result<AST> {
+        // fails on a plain symbol, even with the same sort
+        let f = self.ctx.api_cstor(name, &args, ret)
+            .map_err(|_| format!("`{}` is already declared", name))?;
+        Ok(self.declare_sym(name, f, args, ret))
+    }
+
+    fn declare_sym(&mut self, name: &str, f: AST, args: Vec<AST>, ret: AST) -> AST {
+        if args.len() == 0 {
+            self.consts.push((name.to_string(), f));
+        }
+        self.declare(name, Decl::Fun{f, args, ret});
+        f
+    }
+
+    fn declare_datatypes(&mut self, sorts: &[Sexp], cstors: &[Sexp]) -> Result<()> {
+        // declare all sorts first, they can be mutually recursive
+        for s in sorts.iter() {
+            match s {
+                Sexp::List(l) if l.len() == 2 => {
+                    if atom(&l[1])? != "0" {
+                        return Err("parametric datatypes are not supported".to_string())
+                    }
+                    self.declare_sort(atom(&l[0])?)?;
+                },
+                _ => return Err("invalid datatype sort declaration".to_string()),
+            }
+        }
+        for (s, cs) in sorts.iter().zip(cstors.iter()) {
+            let ty = match s { Sexp::List(l) => self.sort(&l[0])?, _ => unreachable!() };
+            let cs = match cs {
+                Sexp::List(cs) => cs,
+                _ => return Err("invalid list of constructors".to_string()),
+            };
+            let mut fs = vec![];
+            for c in cs.iter() {
+                let (name, sels) = match c {
+                    Sexp::List(l) if l.len() > 0 => (atom(&l[0])?, &l[1..]),
+                    _ => return Err("invalid constructor".to_string()),
+                };
+                let mut args = vec![];
+                for sel in sels.iter() {
+                    match sel {
+                        Sexp::List(l) if l.len() == 2 => {
+                            args.push(self.sort(&l[1])?);
+                            self.declare(atom(&l[0])?, Decl::Selector);
+                        },
+                        _ => return Err("invalid selector".to_string()),
+                    }
+                }
+                let n_args = args.len();
+                fs.push((self.declare_cstor(name, args, ty)?, n_args));
+            }
+            if fs.len() > 0 && fs.iter().all(|&(_, n)| n == 0) {
+                self.enums.insert(ty, fs.into_iter().map(|(f, _)| f).collect());
+            } else {
+                self.datatypes.insert(ty);
+            }
+        }
+        Ok(())
+    }
+
+    fn sort(&self, e: &Sexp) -> Result<AST> {
+        let name = atom(e)?;
+        if name == "Bool" { return Ok(self.ctx.api_ty_bool()) }
+        match self.decls.get(name) {
+            Some(Decl::Sort(ty)) => Ok(*ty),
+            _ => Err(format!("unknown sort `{}`", name)),
+        }
+    }
+
+    fn fresh_lit(&mut self) -> Lit { self.s.api_make_lit() }
+
+    fn add_clause(&mut self, c: &[Lit]) {
+        for &lit in c.iter() { self.s.api_add_clause_lit(lit) }
+        self.s.api_add_clause();
+    }
+
+    fn lit_true(&mut self) -> Lit {
+        match self.lit_true {
+            Some(lit) => lit,
+            None => {
+                let lit = self.fresh_lit();
+                self.add_clause(&[lit]);
+                self.lit_true = Some(lit);
+                lit
+            }
+        }
+    }
+
+    fn sort_of(&self, v: Val) -> AST {
+        match v {
+            Val::Term(t) => self.ctx.m.ty(&t).expect("untyped term"),
+            Val::Lit(_) => self.ctx.api_ty_bool(),
+        }
+    }
+
+    fn is_bool(&self, v: Val) -> bool { self.sort_of(v) == self.ctx.api_ty_bool() }
+
+    fn to_lit(&mut self, v: Val) -> Result<Lit> {
+        if ! self.is_bool(v) {
+            return Err("expected a boolean expression".to_string())
+        }
+        Ok(match v {
+            Val::Lit(lit) => lit,
+            Val::Term(t) if t == self.ctx.b.true_ => self.lit_true(),
+            Val::Term(t) if t == self.ctx.b.false_ => neg(self.lit_true()),
+            Val::Term(t) => {
+                if let Some(&lit) = self.term_lits.get(&t) { return Ok(lit) }
+                let lit = self.s.api_make_term_lit(self.ctx, t);
+                self.term_lits.insert(t, lit);
+                self.add_enum_clauses(t)?;
+                lit
+            },
+        })
+    }
+
+    // each subterm of `t` of an enum sort is one of its constructors
+    fn add_enum_clauses(&mut self, t: AST) -> Result<()> {
+        for u in self.ctx.api_subterms(t) {
+            let cstors = match self.ctx.m.ty(&u).and_then(|ty| self.enums.get(&ty)) {
+                Some(cs) => cs.clone(),
+                None => continue,
+            };
+            if self.ctx.is_cstor(&u) || ! self.covered.insert(u) { continue }
+            let mut c = Vec::with_capacity(cstors.len());
+            for f in cstors {
+                let eq = self.ctx.api_eq(u, f).map_err(|e| e.to_string())?;
+                c.push(self.to_lit(Val::Term(eq))?);
+            }
+            self.add_clause(&c);
+        }
+        Ok(())
+    }
+
+    /// A term for `v`, naming boolean formulas with a fresh constant.
+    fn to_term(&mut self, v: Val) -> Result<AST> {
+        match v {
+            Val::Term(t) => {
+                // register its literal, so that `get-model` knows its value
+                if self.is_bool(v) { self.to_lit(v)?; }
+                Ok(t)
+            },
+            Val::Lit(lit) => {
+                if let Some(&t) = self.lit_terms.get(&lit.to_int()) { return Ok(t) }
+                let bool_ = self.ctx.api_ty_bool();
+                let t = self.fresh_const(bool_);
+                let t_lit = self.to_lit(Val::Term(t))?;
+                self.add_clause(&[neg(t_lit), lit]);
+                self.add_clause(&[t_lit, neg(lit)]);
+                self.lit_terms.insert(lit.to_int(), t);
+                Ok(t)
+            },
+        }
+    }
+
+    fn fresh_const(&mut self, ty: AST) -> AST {
+        // skip the names taken by the script
+        loop {
+            self.n_fresh += 1;
+            let name = format!("_batsmt_fresh_{}", self.n_fresh);
+            if self.decls.contains_key(&name) { continue }
+            if let Ok(t) = self.ctx.api_const(&name, &[], ty) { return t }
+        }
+    }
+
+    // tseitin encodings
+
+    fn mk_and(&mut self, lits: &[Lit]) -> Lit {
+        match lits {
+            [] => self.lit_true(),
+            [lit] => *lit,
+            _ => {
+                let x = self.fresh_lit();
+                let mut c = vec![x];
+                for &lit in lits.iter() {
+                    self.add_clause(&[neg(x), lit]);
+                    c.push(neg(lit));
+                }
+                self.add_clause(&c);
+                x
+            }
+        }
+    }
+
+    fn mk_or(&mut self, lits: &[Lit]) -> Lit {
+        let lits: Vec<Lit> = lits.iter().map(|&l| neg(l)).collect();
+        neg(self.mk_and(&lits))
+    }
+
+    fn mk_xor(&mut self, a: Lit, b: Lit) -> Lit {
+        let x = self.fresh_lit();
+        self.add_clause(&[neg(x), a, b]);
+        self.add_clause(&[neg(x), neg(a), neg(b)]);
+        self.add_clause(&[x, neg(a), b]);
+        self.add_clause(&[x, a, neg(b)]);
+        x
+    }
+
+    fn mk_ite(&mut self, c: Lit, a: Lit, b: Lit) -> Lit {
+        let x = self.fresh_lit();
+        self.add_clause(&[neg(c), neg(a), x]);
+        self.add_clause(&[neg(c), a, neg(x)]);
+        self.add_clause(&[c, neg(b), x]);
+        self.add_clause(&[c, b, neg(x)]);
+        x
+    }
+
+    fn mk_eq(&mut self, a: Val, b: Val) -> Result<Val> {
+        if self.sort_of(a) != self.sort_of(b) {
+            return Err("equality between terms of different sorts".to_string())
+        }
+        if self.is_bool(a) {
+            let a = self.to_lit(a)?;
+            let b = self.to_lit(b)?;
+            Ok(Val::Lit(neg(self.mk_xor(a, b))))
+        } else {
+            let a = self.to_term(a)?;
+            let b = self.to_term(b)?;
+            let eq = self.ctx.api_eq(a, b).map_err(|e| e.to_string())?;
+            // register its literal, for `get-model`
+            self.to_lit(Val::Term(eq))?;
+            Ok(Val::Term(eq))
+        }
+    }
+
+    /// Evaluate `e`, with `env` containing `let`-bound variables.
+    fn eval(&mut self, e: &Sexp, env: &mut Vec<(String, Val)>) -> Result<Val> {
+        let n_env = env.len();
+        let r = self.eval_loop(e, env);
+        env.truncate(n_env); // if it failed inside a `let`
+        r
+    }
+
+    // uses an explicit stack of frames, as expressions can be nested
+    // arbitrarily deep
+    fn eval_loop(&mut self, e: &Sexp, env: &mut Vec<(String, Val)>) -> Result<Val> {
+        let mut stack: Vec<Frame> = vec![];
+        let mut e = e;
+        'eval: loop {
+            // go down into `e` until we have a value
+            let mut v = match e {
+                Sexp::Str(_) => return Err("strings are not supported".to_string()),
+                Sexp::Atom(s) => self.eval_atom(s, env)?,
+                Sexp::List(l) if l.len() == 0 => return Err("empty application".to_string()),
+                Sexp::List(l) => {
+                    let head = atom(&l[0])?;
+                    let args = &l[1..];
+                    match head {
+                        "let" => match args {
+                            [Sexp::List(bs), body] if bs.len() == 0 => {
+                                stack.push(Frame::LetBody{n_env: env.len()});
+                                e = body;
+                                continue 'eval
+                            },
+                            [Sexp::List(bs), body] => {
+                                e = binding(&bs[0])?.1;
+                                stack.push(Frame::Let{bs, body, vals: vec![]});
+                                continue 'eval
+                            },
+                            _ => return Err("invalid let".to_string()),
+                        },
+                        "!" => match args {
+                            [e2, ..] => { e = e2; continue 'eval }, // ignore attributes
+                            _ => return Err("invalid annotation".to_string()),
+                        },
+                        _ if args.len() == 0 => self.eval_app(head, &[])?,
+                        _ => {
+                            e = &args[0];
+                            stack.push(Frame::App{head, args, vals: vec![]});
+                            continue 'eval
+                        },
+                    }
+                },
+            };
+            // go up with `v`
+            loop {
+                match stack.pop() {
+                    None => return Ok(v),
+                    Some(Frame::App{head, args, mut vals}) => {
+                        vals.push(v);
+                        if vals.len() < args.len() {
+                            e = &args[vals.len()];
+                            stack.push(Frame::App{head, args, vals});
+                            continue 'eval
+                        }
+                        v = self.eval_app(head, &vals)?;
+                    },
+                    Some(Frame::Let{bs, body, mut vals}) => {
+                        // bindings are evaluated in the outer environment
+                        vals.push((binding(&bs[vals.len()])?.0.to_string(), v));
+                        if vals.len() < bs.len() {
+                            e = binding(&bs[vals.len()])?.1;
+                            stack.push(Frame::Let{bs, body, vals});
+                        } else {
+                            stack.push(Frame::LetBody{n_env: env.len()});
+                            env.extend(vals);
+                            e = body;
+                        }
+                        continue 'eval
+                    },
+                    Some(Frame::LetBody{n_env}) => env.truncate(n_env),
+                }
+            }
+        }
+    }
+
+    fn eval_atom(&mut self, s: &str, env: &[(String, Val)]) -> Result<Val> {
+        if let Some((_, v)) = env.iter().rev().find(|(x,_)| x == s) {
+            return Ok(*v)
+        }
+        match s {
+            "true" => return Ok(Val::Term(self.ctx.api_bool(true))),
+            "false" => return Ok(Val::Term(self.ctx.api_bool(false))),
+            _ => (),
+        }
+        match self.decls.get(s) {
+            Some(Decl::Fun{f, args, ..}) if args.len() == 0 => Ok(Val::Term(*f)),
+            Some(Decl::Fun{..}) => Err(format!("`{}` expects arguments", s)),
+            Some(Decl::Def(v)) => Ok(*v),
+            _ => Err(format!("unknown constant `{}`", s)),
+        }
+    }
+
+    fn lits(&mut self, args: &[Val]) -> Result<Vec<Lit>> {
+        args.iter().map(|&v| self.to_lit(v)).collect()
+    }
+
+    fn eval_app(&mut self, head: &str, args: &[Val]) -> Result<Val> {
+        match head {
+            "not" => match args {
+                [Val::Lit(lit)] => Ok(Val::Lit(neg(*lit))),
+                [v] => {
+                    self.to_lit(*v)?; // check type
+                    let t = self.to_term(*v)?;
+                    Ok(Val::Term(self.ctx.api_not(t)))
+                },
+                _ => Err("not expects one argument".to_string()),
+            },
+            "and" => { let l = self.lits(args)?; Ok(Val::Lit(self.mk_and(&l))) },
+            "or" => { let l = self.lits(args)?; Ok(Val::Lit(self.mk_or(&l))) },
+            "=>" if args.len() >= 2 => {
+                let mut l = self.lits(args)?;
+                let n = l.len();
+                for lit in l[.. n-1].iter_mut() { *lit = neg(*lit) }
+                Ok(Val::Lit(self.mk_or(&l)))
+            },
+            "xor" if args.len() >= 2 => {
+                let l = self.lits(args)?;
+                let mut x = l[0];
+                for &lit in l[1..].iter() { x = self.mk_xor(x, lit) }
+                Ok(Val::Lit(x))
+            },
+            "ite" => match args {
+                [c, a, b] => {
+                    let c = self.to_lit(*c)?;
+                    if self.is_bool(*a) {
+                        let a = self.to_lit(*a)?;
+                        let b = self.to_lit(*b)?;
+                        Ok(Val::Lit(self.mk_ite(c, a, b)))
+                    } else {
+                        let ty = self.sort_of(*a);
+                        let x = Val::Term(self.fresh_const(ty));
+                        let eq_a = self.mk_eq(x, *a)?;
+                        let eq_a = self.to_lit(eq_a)?;
+                        let eq_b = self.mk_eq(x, *b)?;
+                        let eq_b = self.to_lit(eq_b)?;
+                        self.add_clause(&[neg(c), eq_a]);
+                        self.add_clause(&[c, eq_b]);
+                        Ok(x)
+                    }
+                },
+                _ => Err("ite expects 3 arguments".to_string()),
+            },
+            "=" if args.len() >= 2 => {
+                if args.len() == 2 { return self.mk_eq(args[0], args[1]) }
+                let mut l = vec![];
+                for w in args.windows(2) {
+                    let eq = self.mk_eq(w[0], w[1])?;
+                    l.push(self.to_lit(eq)?);
+                }
+                Ok(Val::Lit(self.mk_and(&l)))
+            },
+            "distinct" if args.len() >= 2 => {
+                let mut l = vec![];
+                for i in 0 .. args.len() {
+                    for j in i+1 .. args.len() {
+                        let eq = self.mk_eq(args[i], args[j])?;
+                        l.push(neg(self.to_lit(eq)?));
+                    }
+                }
+                Ok(Val::Lit(self.mk_and(&l)))
+            },
+            _ => {
+                let (f, ty_args) = match self.decls.get(head) {
+                    Some(Decl::Fun{f, args, ..}) => (*f, args.clone()),
+                    Some(Decl::Selector) => {
+                        return Err(format!("selector `{}` is not supported", head))
+                    },
+                    _ => return Err(format!("unknown function `{}`", head)),
+                };
+                if ty_args.len() != args.len() {
+                    return Err(format!("`{}` expects {} arguments", head, ty_args.len()))
+                }
+                let mut ts = Vec::with_capacity(args.len());
+                for (&a, &ty) in args.iter().zip(ty_args.iter()) {
+                    if self.sort_of(a) != ty {
+                        return Err(format!("ill-sorted argument for `{}`", head))
+                    }
+                    ts.push(self.to_term(a)?);
+                }
+                self.ctx.api_app(f, &ts).map(Val::Term).map_err(|e| e.to_string())
+            },
+        }
+    }
+
+    fn assert(&mut self, e: &Sexp) -> Result<()> {
+        // look for `(! e :named n)`
+        let name = match e {
+            Sexp::List(l) if l.len() > 0 && atom(&l[0]).ok() == Some("!") => {
+                l.windows(2)
+                    .find(|w| atom(&w[0]).ok() == Some(":named"))
+                    .map(|w| atom(&w[1]).map(|s| s.to_string()))
+                    .transpose()?
+            },
+            _ => None,
+        };
+        let v = self.eval(e, &mut vec![])?;
+        let lit = self.to_lit(v)?;
+        let mut c = vec![lit];
+        if let Some(sc) = self.scopes.last() { c.push(neg(sc.act)) }
+        if let Some(name) = name {
+            // assumed at each `check-sat`, so it can be part of unsat cores
+            let n_lit = self.fresh_lit();
+            c.push(neg(n_lit));
+            self.named.push((name, n_lit));
+        }
+        self.add_clause(&c);
+        Ok(())
+    }
+
+    fn check_sat(&mut self, assumptions: &[Lit]) -> Result<()> {
+        for sc in self.scopes.iter() { self.s.api_add_assumption(sc.act) }
+        for (_, lit) in self.named.iter() { self.s.api_add_assumption(*lit) }
+        for &lit in assumptions.iter() { self.s.api_add_assumption(lit) }
+        let r = self.s.api_solve(self.ctx);
+        // acyclicity and exhaustiveness of datatypes are not enforced
+        if r && self.datatypes.len() > 0 && ! Model::new(self).is_datatype_model(self) {
+            self.last_res = None;
+            self.out.push_str("unknown\n");
+            return Ok(())
+        }
+        self.last_res = Some(r);
+        let _ = writeln!(self.out, "{}", if r { "sat" } else { "unsat" });
+        Ok(())
+    }
+
+    fn push(&mut self) {
+        let act = self.fresh_lit();
+        let sc = Scope{
+            act, decls: vec![], n_consts: self.consts.len(), n_named: self.named.len(),
+        };
+        self.scopes.push(sc);
+    }
+
+    fn pop(&mut self) {
+        let sc = self.scopes.pop().unwrap();
+        // disable the scope's assertions for good
+        self.add_clause(&[neg(sc.act)]);
+        for (name, d) in sc.decls.into_iter().rev() {
+            match d {
+                Some(d) => { self.decls.insert(name, d); },
+                None => { self.decls.remove(&name); },
+            }
+        }
+        self.consts.truncate(sc.n_consts);
+        self.named.truncate(sc.n_named);
+        self.last_res = None;
+    }
+
+    fn get_model(&mut self) -> Result<()> {
+        if self.last_res != Some(true) {
+            return Err("no model available".to_string())
+        }
+        let bool_ = self.ctx.api_ty_bool();
+        let mut m = Model::new(self);
+        let mut out = String::from("(\n");
+        for (name, c) in self.consts.iter() {
+            if self.ctx.is_cstor(c) { continue }
+            let ty = self.ctx.m.ty(c).unwrap();
+            out.push_str("  (define-fun ");
+            pp_symbol(&mut out, name);
+            out.push_str(" () ");
+            if ty == bool_ {
+                // constants the solver never saw can take any value
+                let b = match self.term_lits.get(c) {
+                    Some(&lit) => self.s.api_value(lit) == Lbool::TRUE,
+                    None => false,
+                };
+                let _ = write!(out, "Bool {})\n", b);
+            } else {
+                pp_symbol(&mut out, self.ctx.api_const_get_name(ty).unwrap_or("?"));
+                out.push(' ');
+                out.push_str(&m.value(self.ctx, *c));
+                out.push_str(")\n");
+            }
+        }
+        out.push_str(")\n");
+        self.out.push_str(&out);
+        Ok(())
+    }
+
+    fn get_unsat_core(&mut self) -> Result<()> {
+        if self.last_res != Some(false) {
+            return Err("no unsat core available".to_string())
+        }
+        let Driver{named, s, out, ..} = self;
+        let names: Vec<&str> =
+            named.iter()
+            .filter(|(_, lit)| s.api_unsat_core_contains(*lit))
+            .map(|(name, _)| name.as_str())
+            .collect();
+        let _ = writeln!(out, "({})", names.join(" "));
+        Ok(())
+    }
+}
+
+/// Values of non-boolean terms in the solver's model.
+///
+/// The solver does not expose the congruence closure's classes, so they
+/// are rebuilt from the values of the literals: terms are merged when an
+/// equality between them is true, and then by congruence and injectivity
+/// of constructors.
+struct Model {
+    terms: Vec<AST>, // non-boolean terms the solver saw
+    parent: FxHashMap<AST, AST>, // union-find
+    cstor: FxHashMap<AST, AST>, // class -> a term of it headed by a constructor
+    values: FxHashMap<AST, String>, // class -> its value
+    n_abstract: FxHashMap<AST, usize>, // sort -> number of abstract values
+}
+
+impl Model {
+    fn new(d: &Driver) -> Self {
+        let ctx = &*d.ctx;
+        let mut m = Model{
+            terms: vec![], parent: FxHashMap::default(), cstor: FxHashMap::default(),
+            values: FxHashMap::default(), n_abstract: FxHashMap::default(),
+        };
+        let mut apps = vec![];
+        let mut seen = FxHashSet::default();
+        for (&t, &lit) in d.term_lits.iter() {
+            let value = d.s.api_value(lit) == Lbool::TRUE;
+            // boolean terms are in the class of their value
+            m.union(t, if value { ctx.b.true_ } else { ctx.b.false_ });
+            if let AstView::App{f, args} = ctx.m.view(&t) {
+                if *f == ctx.b.eq && value { m.union(args[0], args[1]) }
+            }
+            for u in ctx.api_subterms(t) {
+                if ! seen.insert(u) { continue }
+                if ! ctx.is_boolean_term(&u) { m.terms.push(u) }
+                if let AstView::App{f, ..} = ctx.m.view(&u) {
+                    if *f != ctx.b.eq && *f != ctx.b.not_ { apps.push(u) }
+                }
+            }
+        }
+        // congruence and injectivity, until nothing changes
+        loop {
+            let mut changed = false;
+            let mut sigs: FxHashMap<(AST, Vec<AST>), AST> = FxHashMap::default();
+            for &t in apps.iter() {
+                if let AstView::App{f, args} = ctx.m.view(&t) {
+                    let sig = (*f, args.iter().map(|&a| m.find(a)).collect());
+                    match sigs.get(&sig) {
+                        Some(&u) => changed |= m.union(t, u),
+                        None => { sigs.insert(sig, t); },
+                    }
+                }
+            }
+            let mut cstors: FxHashMap<AST, AST> = FxHashMap::default();
+            for &t in apps.iter() {
+                if let AstView::App{f, args} = ctx.m.view(&t) {
+                    if ! ctx.is_cstor(f) { continue }
+                    let r = m.find(t);
+                    match cstors.get(&r).map(|u| ctx.m.view(u)) {
+                        Some(AstView::App{f: f2, args: args2}) if f2 == f => {
+                            for (&a, &b) in args.iter().zip(args2.iter()) {
+                                changed |= m.union(a, b);
+                            }
+                        },
+                        Some(_) => (), // cannot happen in a model
+                        None => { cstors.insert(r, t); },
+                    }
+                }
+            }
+            if ! changed { break }
+        }
+        for &t in apps.iter().chain(m.terms.iter()).chain(d.consts.iter().map(|(_, c)| c)) {
+            let head = match ctx.m.view(&t) {
+                AstView::App{f, ..} => *f,
+                _ => t,
+            };
+            if ctx.is_cstor(&head) {
+                let r = m.find(t);
+                m.cstor.insert(r, t);
+            }
+        }
+        m
+    }
+
+    /// Is every term of a datatype equal to a finite constructor term?
+    ///
+    /// Terms of other datatypes than enums can be in a class without
+    /// constructor term, or be equal to one of their strict subterms.
+    fn is_datatype_model(&self, d: &Driver) -> bool {
+        let ctx = &*d.ctx;
+        let is_dt = |u: &AST| ctx.m.ty(u).map_or(false, |ty| d.datatypes.contains(&ty));
+        // DFS on classes; `false` while on the current path, `true` once done
+        let mut state: FxHashMap<AST, bool> = FxHashMap::default();
+        for &t in self.terms.iter() {
+            if ! is_dt(&t) { continue }
+            let mut stack = vec![(self.find(t), false)];
+            while let Some((r, done)) = stack.pop() {
+                if done { state.insert(r, true); continue }
+                match state.get(&r) {
+                    Some(true) => continue,
+                    Some(false) => return false, // cycle
+                    None => (),
+                }
+                let args = match self.cstor.get(&r).map(|c| ctx.m.view(c)) {
+                    Some(AstView::App{args, ..}) => &args[..],
+                    Some(_) => &[][..],
+                    None if is_dt(&r) => return false,
+                    None => &[][..],
+                };
+                state.insert(r, false);
+                stack.push((r, true));
+                for a in args.iter() { stack.push((self.find(*a), false)) }
+            }
+        }
+        true
+    }
+
+    fn find(&self, mut t: AST) -> AST {
+        while let Some(&u) = self.parent.get(&t) { t = u }
+        t
+    }
+
+    // merge the classes of `a` and `b`, returns `true` if they were distinct
+    fn union(&mut self, a: AST, b: AST) -> bool {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b { return false }
+        self.parent.insert(a, b);
+        true
+    }
+
+    /// Value of `t`, as a SMT-LIB term: a constructor term for datatypes,
+    /// and an abstract value `(as @S_n S)` for other sorts.
+    fn value(&mut self, ctx: &Ctx, t: AST) -> String {
+        let r = self.find(t);
+        // constructor terms can be deep, so compute the values of
+        // arguments first with an explicit stack
+        let mut stack = vec![(r, false)];
+        let mut on_stack = FxHashSet::default();
+        while let Some((u, args_done)) = stack.pop() {
+            if self.values.contains_key(&u) { continue }
+            let args: Vec<AST> = match self.cstor.get(&u).map(|c| ctx.m.view(c)) {
+                Some(AstView::App{args, ..}) => args.iter().map(|&a| self.find(a)).collect(),
+                _ => vec![],
+            };
+            if args_done {
+                let v = match self.cstor.get(&u).cloned() {
+                    None => self.abstract_value(ctx, u),
+                    Some(c) if args.len() == 0 => {
+                        let mut s = String::new();
+                        pp_symbol(&mut s, ctx.api_const_get_name(c).unwrap_or("?"));
+                        s
+                    },
+                    Some(c) => {
+                        let f = ctx.api_app_get_fun(c).unwrap();
+                        let mut s = String::from("(");
+                        pp_symbol(&mut s, ctx.api_const_get_name(f).unwrap_or("?"));
+                        for a in args.iter() {
+                            s.push(' ');
+                            s.push_str(&self.values[a]);
+                        }
+                        s.push(')');
+                        s
+                    },
+                };
+                self.values.insert(u, v);
+            } else {
+                on_stack.insert(u);
+                stack.push((u, true));
+                for a in args {
+                    // a cyclic value cannot be printed as a term
+                    if on_stack.contains(&a) && ! self.values.contains_key(&a) {
+                        let v = self.abstract_value(ctx, a);
+                        self.values.insert(a, v);
+                    }
+                    if ! self.values.contains_key(&a) { stack.push((a, false)) }
+                }
+            }
+        }
+        self.values[&r].clone()
+    }
+
+    fn abstract_value(&mut self, ctx: &Ctx, r: AST) -> String {
+        let ty = ctx.m.ty(&r).expect("untyped term");
+        let n = self.n_abstract.entry(ty).or_insert(0);
+        let ty_name = ctx.api_const_get_name(ty).unwrap_or("?");
+        let mut s = String::from("(as ");
+        pp_symbol(&mut s, &format!("@{}_{}", ty_name, n));
+        s.push(' ');
+        pp_symbol(&mut s, ty_name);
+        s.push(')');
+        *n += 1;
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(src: &str) -> String { parse_and_run(&mut Ctx::new(), src) }
+
+    // value of constant `name` of sort `ty` in the output of `get-model`
+    fn model_value(out: &str, name: &str, ty: &str) -> String {
+        let prefix = format!("  (define-fun {} () {} ", name, ty);
+        let line = out.lines().find(|l| l.starts_with(&prefix)).expect("no such constant");
+        line[prefix.len() .. line.len()-1].to_string()
+    }
+
+    #[test]
+    fn ignored_commands() {
+        assert_eq!(run("(set-logic QF_UF) (set-info :status sat) (set-option :x 1)"), "");
+    }
+
+    #[test]
+    fn echo_and_exit() {
+        assert_eq!(run(r#"(echo "a ""b""") (exit) (echo "c")"#), "\"a \"b\"\"\n");
+    }
+
+    #[test]
+    fn declare_and_check() {
+        let out = run("
+            (declare-sort U 0)
+            (declare-const a U) (declare-const b U)
+            (declare-fun f (U) U) (declare-fun p (U) Bool)
+            (assert (= (f a) b))
+            (assert (p (f a)))
+            (check-sat)
+            (assert (not (p b)))
+            (check-sat)");
+        assert_eq!(out, "sat\nunsat\n");
+    }
+
+    #[test]
+    fn define_fun() {
+        let out = run("
+            (declare-sort U 0)
+            (declare-const a U) (declare-const p Bool)
+            (define-fun b () U a)
+            (define-fun q () Bool (and p (= a b)))
+            (assert (not q))
+            (check-sat)
+            (assert p)
+            (check-sat)");
+        assert_eq!(out, "sat\nunsat\n");
+    }
+
+    #[test]
+    fn connectives() {
+        let out = run("
+            (declare-const p Bool) (declare-const q Bool)
+            (assert (=> p q))
+            (assert (xor p (not q)))
+            (assert (distinct p (ite q false true)))
+            (assert (let ((x p) (y q)) (or (and x y) (= x y))))
+            (check-sat)");
+        assert_eq!(out, "sat\n");
+    }
+
+    #[test]
+    fn check_sat_assuming() {
+        let out = run("
+            (declare-const p Bool)
+            (assert p)
+            (check-sat-assuming ((not p)))
+            (check-sat)");
+        assert_eq!(out, "unsat\nsat\n");
+    }
+
+    #[test]
+    fn push_pop() {
+        let out = run("
+            (declare-const p Bool)
+            (push 1)
+            (declare-const q Bool)
+            (assert (and p q (not p)))
+            (check-sat)
+            (pop 1)
+            (check-sat)
+            (assert q)");
+        assert_eq!(out, "unsat\nsat\n(error \"unknown constant `q`\")\n");
+    }
+
+    #[test]
+    fn unsat_core() {
+        let out = run("
+            (declare-const p Bool) (declare-const q Bool)
+            (assert (! p :named a1))
+            (assert (! q :named a2))
+            (assert (! (not p) :named a3))
+            (check-sat)
+            (get-unsat-core)");
+        assert_eq!(out, "unsat\n(a1 a3)\n");
+    }
+
+    #[test]
+    fn model_bool() {
+        let out = run("
+            (declare-const p Bool) (declare-const q Bool)
+            (assert (and p (not q)))
+            (check-sat)
+            (get-model)");
+        assert_eq!(model_value(&out, "p", "Bool"), "true");
+        assert_eq!(model_value(&out, "q", "Bool"), "false");
+    }
+
+    #[test]
+    fn model_uninterpreted() {
+        let out = run("
+            (declare-sort U 0)
+            (declare-const a U) (declare-const b U) (declare-const c U)
+            (declare-fun f (U) U)
+            (assert (= (f a) b))
+            (assert (= a c))
+            (assert (not (= (f c) a)))
+            (check-sat)
+            (get-model)");
+        let (a, b, c) = (model_value(&out, "a", "U"), model_value(&out, "b", "U"),
+            model_value(&out, "c", "U"));
+        assert!(a.starts_with("(as @U_"));
+        assert_eq!(a, c);
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn model_datatype() {
+        let out = run("
+            (declare-sort U 0)
+            (declare-datatypes ((L 0)) (((nil) (cons (hd U) (tl L)))))
+            (declare-const u U) (declare-const x L) (declare-const y L)
+            (assert (= x (cons u y)))
+            (assert (= y nil))
+            (check-sat)
+            (get-model)");
+        assert_eq!(model_value(&out, "y", "L"), "nil");
+        let u = model_value(&out, "u", "U");
+        assert_eq!(model_value(&out, "x", "L"), format!("(cons {} nil)", u));
+        assert!(! out.contains("define-fun nil"));
+    }
+
+    #[test]
+    fn enum_datatype() {
+        let src = "
+            (declare-datatypes ((C 0)) (((red) (green) (blue))))
+            (declare-const x C)
+            (assert (not (= x red)))
+            (assert (not (= x green)))
+            (check-sat)
+            (get-model)
+            (assert (not (= x blue)))
+            (check-sat)";
+        let out = run(src);
+        assert_eq!(model_value(&out, "x", "C"), "blue");
+        assert!(out.starts_with("sat\n"));
+        assert!(out.ends_with("unsat\n"));
+    }
+
+    #[test]
+    fn datatype_unknown() {
+        // `x` would be an infinite term
+        let out = run("
+            (declare-datatypes ((L 0)) (((nil) (cons (hd Bool) (tl L)))))
+            (declare-const x L)
+            (assert (= x (cons true x)))
+            (check-sat)
+            (get-model)");
+        assert_eq!(out, "unknown\n(error \"no model available\")\n");
+        // `x` and `y` are not known to be constructor terms
+        let out = run("
+            (declare-datatypes ((L 0)) (((nil) (cons (hd Bool) (tl L)))))
+            (declare-const x L) (declare-const y L)
+            (assert (not (= x y)))
+            (check-sat)");
+        assert_eq!(out, "unknown\n");
+    }
+
+    #[test]
+    fn deep_nesting() {
+        // would overflow the stack if parsing, evaluating or dropping recursed
+        let n = 200_000;
+        let mut src = String::from("(declare-const p Bool) (declare-const q Bool) (assert ");
+        for _ in 0 .. n { src.push_str("(and q ") }
+        src.push('p');
+        for _ in 0 .. n { src.push(')') }
+        src.push_str(") (check-sat)");
+        assert_eq!(run(&src), "sat\n");
+    }
+
+    #[test]
+    fn stops_at_first_error() {
+        assert_eq!(run(r#"(echo "a") (foo) (echo "b")"#),
+            "\"a\"\n(error \"unsupported command `foo`\")\n");
+    }
+
+    #[test]
+    fn errors() {
+        let cases = [
+            ("(check-sat", "unclosed parenthesis"),
+            (")", "unexpected ')'"),
+            ("(declare-const |x Bool)", "unclosed quoted symbol"),
+            ("(declare-const |x\\y| Bool)", "quoted symbols cannot contain `\\`"),
+            ("(echo \"abc)", "unclosed string"),
+            ("foo", "expected a command, got `foo`"),
+            ("(echo foo)", "echo expects a string"),
+            ("(declare-sort U 1)", "parametric sorts are not supported"),
+            ("(declare-sort)", "invalid declare-sort"),
+            ("(declare-fun f (Bool) Bool) (declare-sort f 0)", "`f` is already a function"),
+            ("(declare-sort U 0) (declare-sort U 0)", "`U` is already declared"),
+            ("(declare-sort U 0) (declare-datatypes ((U 0)) (((mk))))", "`U` is already declared"),
+            ("(declare-const x)", "invalid declare-const"),
+            ("(declare-fun f Bool Bool)", "invalid declare-fun"),
+            ("(declare-sort U 0) (declare-const x Bool) (declare-const x U)",
+             "`x` is already declared with another sort"),
+            ("(declare-const x U)", "unknown sort `U`"),
+            ("(declare-datatypes ((D 0)))", "invalid declare-datatypes"),
+            ("(declare-datatypes ((D 1)) (((mk))))", "parametric datatypes are not supported"),
+            ("(declare-datatypes (D) (((mk))))", "invalid datatype sort declaration"),
+            ("(declare-datatypes ((D 0)) (mk))", "invalid list of constructors"),
+            ("(declare-datatypes ((D 0)) ((mk)))", "invalid constructor"),
+            ("(declare-datatypes ((D 0)) (((mk x))))", "invalid selector"),
+            ("(declare-const D Bool) (declare-datatypes ((D 0)) (((mk))))",
+             "`D` is already a function"),
+            // `c` is still a plain constant of `D` in the context after `pop`
+            ("(push 1) (declare-sort D 0) (declare-const c D) (pop 1) \
+              (declare-datatypes ((D 0)) (((c))))", "`c` is already declared"),
+            ("(assert)", "invalid assert"),
+            ("(check-sat-assuming p)", "invalid check-sat-assuming"),
+            ("(push x)", "invalid number `x`"),
+            ("(push 1 2)", "expected a number of scopes"),
+            ("(pop 1)", "cannot pop 1 scopes"),
+            ("(get-model)", "no model available"),
+            ("(get-unsat-core)", "no unsat core available"),
+            ("(get-value (x))", "unsupported command `get-value`"),
+            ("(assert \"s\")", "strings are not supported"),
+            ("(assert ())", "empty application"),
+            ("(assert ((x)))", "expected a symbol, got a list"),
+            ("(assert (let x true))", "invalid let"),
+            ("(assert (let (x) true))", "invalid let binding"),
+            ("(assert (!))", "invalid annotation"),
+            ("(assert y)", "unknown constant `y`"),
+            ("(declare-fun f (Bool) Bool) (assert f)", "`f` expects arguments"),
+            ("(assert (g true))", "unknown function `g`"),
+            ("(declare-fun f (Bool) Bool) (assert (f true true))", "`f` expects 1 arguments"),
+            ("(declare-sort U 0) (declare-fun f (U) Bool) (assert (f true))",
+             "ill-sorted argument for `f`"),
+            ("(declare-datatypes ((D 0)) (((mk (get Bool))))) (declare-const d D) (assert (get d))",
+             "selector `get` is not supported"),
+            ("(declare-sort U 0) (declare-const x U) (assert x)", "expected a boolean expression"),
+            ("(define-fun x () Bool 1 2)", "invalid define-fun"),
+            ("(define-fun f ((x Bool)) Bool x)", "define-fun with parameters is not supported"),
+            ("(declare-sort U 0) (declare-const a U) (define-fun x () Bool a)",
+             "ill-sorted definition of `x`"),
+            ("(declare-sort U 0) (declare-const x U) (assert (= x true))",
+             "equality between terms of different sorts"),
+            ("(assert (not true false))", "not expects one argument"),
+            ("(assert (ite true false))", "ite expects 3 arguments"),
+        ];
+        for (src, msg) in cases.iter() {
+            let out = run(src);
+            assert_eq!(out.lines().last(), Some(format!("(error \"{}\")", msg).as_str()),
+                "for {}", src);
+        }
+    }
+}
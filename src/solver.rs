@@ -1,9 +1,10 @@
 
 use {
+    std::{sync::Arc, time::{Duration, Instant}},
     batsmt_core::{ast, ast_u32::AST},
     batsmt_solver::{self as solver, blit::SatLit},
     batsmt_cc::{self as cc, theories as ccth, Ctx as CCCtx, CCView},
-    crate::ctx::Ctx,
+    crate::{ctx::Ctx, busy::Busy, rng::Rng, error::{Error, Result}},
 };
 
 type MTheories = (ccth::Constructor<AST>, );
@@ -15,12 +16,65 @@ pub type Lit = solver::BLit;
 /// An optional truth value.
 pub type Lbool = solver::solver::lbool;
 
+/// Options for the solver, which can be changed between calls to `solve`.
+#[derive(Copy,Clone,Debug)]
+pub struct Options {
+    /// If non-zero, shuffle the literals of each new clause with this seed.
+    /// This changes which literals are watched, and hence the search.
+    pub seed: u64,
+    /// Call `api_simplify` at the beginning of each call to `api_solve`.
+    pub auto_simplify: bool,
+    /// Keep a copy of each clause, as needed by `clauses` (and hence to
+    /// print the problem or solve it with a portfolio).
+    pub keep_clauses: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options{ seed: 0, auto_simplify: false, keep_clauses: false }
+    }
+}
+
+/// Statistics of a solver.
+#[derive(Copy,Clone,Debug,Default)]
+pub struct Stats {
+    pub n_lits: usize,
+    pub n_clauses: usize,
+    pub n_decisions: usize,
+    pub n_props: usize,
+    pub n_conflicts: usize,
+    /// Number of calls to `api_solve`.
+    pub n_solve: usize,
+    /// Time spent in `api_solve` (SAT and theory together), in seconds.
+    pub solve_time: f64,
+}
+
+/// How a literal was created.
+#[derive(Copy,Clone,Debug)]
+pub enum LitDecl {
+    Fresh(Lit),
+    Term(AST, Lit),
+}
+
 /// The SMT solver.
 pub struct Solver {
     s: solver::Solver<Ctx, Th>,
     cur_clause: Vec<SatLit>,
     assumptions: Vec<SatLit>,
+    lits: Vec<LitDecl>, // literals, in creation order
+    clauses: Vec<Vec<SatLit>>, // clauses added so far, if `opts.keep_clauses`
+    all_clauses_kept: bool, // no clause was added without `opts.keep_clauses`
+    last_assumptions: Vec<SatLit>, // used by the last call to `solve`
+    opts: Options,
+    rng: Rng,
     iter: ast::iter_dag::State<AST, ast::HashSet<AST>>,
+    busy: Busy,
+    ctx_id: u64, // `Ctx::id` of the context it was created with
+    _ctx_token: Arc<()>, // counts this solver in `Ctx::n_solvers`
+    n_clause_lits: usize, // total size of `clauses`
+    n_proved_seen: usize, // prefix of `proved_at_lvl_0` returned by `api_new_proved_at_lvl_0`
+    n_solve: usize,
+    solve_time: Duration,
 }
 
 #[inline]
@@ -33,20 +87,56 @@ fn bool_of_res(r: solver::solver::Res) -> bool {
 
 impl Solver {
     /// Create a new solver with the given context.
-    pub fn new(c: &mut Ctx) -> Self {
+    pub fn new(c: &mut Ctx) -> Self { Self::new_with(c, Options::default()) }
+
+    /// Create a new solver with the given context and options.
+    pub fn new_with(c: &mut Ctx, opts: Options) -> Self {
         let th: Th = cc::CCTheory::new(c);
         let s = solver::Solver::new(c.builtins(), th);
+        let rng = Rng::new(opts.seed);
         Solver{
             s, cur_clause: vec![], assumptions: vec![],
+            lits: vec![], clauses: vec![], all_clauses_kept: true,
+            last_assumptions: vec![], opts, rng,
             iter: ast::iter_dag::new(),
+            busy: Busy::new(), ctx_id: c.id(), _ctx_token: c.solver_token(),
+            n_clause_lits: 0, n_proved_seen: 0,
+            n_solve: 0, solve_time: Duration::from_secs(0),
         }
     }
 
+    /// Check that `c` is the context this solver was created with.
+    pub fn check_ctx(&self, c: &Ctx) -> Result<()> {
+        if c.id() == self.ctx_id { Ok(()) } else { Err(Error::WrongCtx) }
+    }
+
+    /// Flag set while this solver is searching.
+    #[inline]
+    pub fn busy(&self) -> &Busy { &self.busy }
+
+    /// Flag of the solver at `p`, obtained without forming a reference
+    /// to the solver itself (a search might hold a `&mut` to it).
+    #[inline]
+    pub unsafe fn busy_of<'a>(p: *const Solver) -> &'a Busy {
+        &*std::ptr::addr_of!((*p).busy)
+    }
+
+    /// Current options.
+    pub fn options(&self) -> &Options { &self.opts }
+
+    /// Change options. This takes effect at the next call to `api_solve`
+    /// (or `api_add_clause`, for the seed).
+    pub fn api_set_options(&mut self, opts: Options) {
+        if opts.seed != self.opts.seed { self.rng = Rng::new(opts.seed) }
+        self.opts = opts;
+    }
+
     /// Create a new boolean literal.
     #[inline]
     pub fn api_make_lit(&mut self) -> Lit {
         let lit = Lit::from(self.s.new_bool_lit());
         //println!("make-lit {:?}", lit);
+        self.lits.push(LitDecl::Fresh(lit));
         lit
     }
 
@@ -66,14 +156,25 @@ impl Solver {
                 }
             }
         });
+        self.lits.push(LitDecl::Term(t, lit));
         lit
     }
 
+    /// Literals for all the terms in `ts`.
+    pub fn api_make_term_lits(&mut self, ctx: &mut Ctx, ts: &[AST]) -> Vec<Lit> {
+        ts.iter().map(|&t| self.api_make_term_lit(ctx, t)).collect()
+    }
+
     /// Add a new assumption for the next call to `solve`
     pub fn api_add_assumption(&mut self, lit: Lit) {
         self.assumptions.push(lit.0);
     }
 
+    /// Remove and return the assumptions for the next call to `solve`.
+    pub fn api_take_assumptions(&mut self) -> Vec<Lit> {
+        self.assumptions.drain(..).map(Lit::new).collect()
+    }
+
     /// Add a literal to the next call to `add_clause`
     pub fn api_add_clause_lit(&mut self, lit: Lit) {
         self.cur_clause.push(lit.0);
@@ -83,8 +184,16 @@ impl Solver {
     /// 
     /// Returns `true` if satisfiable, `false` otherwise.
     pub fn api_solve(&mut self, c: &mut Ctx) -> bool {
+        let start = Instant::now();
+        self.n_solve += 1;
+        if self.opts.auto_simplify {
+            // if this finds the problem unsat, `solve_with` will too
+            let _ = self.s.sat_simplify();
+        }
         let r = self.s.solve_with(c, &self.assumptions[..]);
+        std::mem::swap(&mut self.assumptions, &mut self.last_assumptions);
         self.assumptions.clear();
+        self.solve_time += start.elapsed();
         bool_of_res(r)
     }
 
@@ -115,6 +224,15 @@ impl Solver {
         self.s.proved_at_lvl_0()
     }
 
+    /// Obtain literals proved at level 0 since the last call to this function.
+    pub fn api_new_proved_at_lvl_0(&mut self) -> &[SatLit] {
+        let proved = self.s.proved_at_lvl_0();
+        // the level 0 trail only grows, but be safe if it is ever shrunk
+        let start = if self.n_proved_seen <= proved.len() { self.n_proved_seen } else { 0 };
+        self.n_proved_seen = proved.len();
+        &proved[start ..]
+    }
+
     /// Obtain value of this literal at level 0.
     #[inline]
     pub fn api_value_lvl_0(&self, lit: Lit) -> Lbool {
@@ -127,16 +245,267 @@ impl Solver {
         self.s.value_lit(lit)
     }
 
+    /// The model as a bitset: bit `v % 8` of byte `v / 8` is set iff
+    /// the variable `v` (for `v` in `1 ..= api_n_lits()`) is true.
+    pub fn api_model_bits(&self) -> Vec<u8> {
+        let n = self.api_n_lits();
+        let mut bits = vec![0u8; n / 8 + 1];
+        for v in 1 ..= n {
+            if self.api_value(Lit::unsafe_from_int(v as i32)) == Lbool::TRUE {
+                bits[v / 8] |= 1 << (v % 8)
+            }
+        }
+        bits
+    }
+
     /// Add the current clause to the SAT solver.
     pub fn api_add_clause(&mut self) {
         //println!("add clause {:?}", &self.cur_clause);
-        self.s.add_bool_clause_reuse(&mut self.cur_clause);
-        self.cur_clause.clear();
+        let mut c = std::mem::replace(&mut self.cur_clause, vec![]);
+        self.add_clause_buf(&mut c);
+        self.cur_clause = c; // keep its capacity
+    }
+
+    // add `c` to the SAT solver, and keep a copy if `opts.keep_clauses`.
+    // `c` is left empty.
+    fn add_clause_buf(&mut self, c: &mut Vec<SatLit>) {
+        if self.opts.keep_clauses {
+            self.clauses.push(c.clone());
+            self.n_clause_lits += c.len();
+        } else {
+            self.all_clauses_kept = false;
+        }
+        if self.opts.seed != 0 {
+            self.rng.shuffle(c);
+        }
+        self.s.add_bool_clause_reuse(c);
+        c.clear();
+    }
+
+    /// Check that `lit` (in the `to_int` form) is a literal of this solver,
+    /// i.e. `0 < |lit| <= api_n_lits()`.
+    pub fn check_lit(&self, lit: i32) -> Result<Lit> {
+        let v = (lit as i64).abs() as usize;
+        if lit != 0 && v <= self.api_n_lits() {
+            Ok(Lit::unsafe_from_int(lit))
+        } else {
+            Err(Error::UnknownLit(lit))
+        }
+    }
+
+    /// Rough estimate of the memory used by this solver, in bytes.
+    pub fn mem_size(&self) -> usize {
+        // per clause: the SAT solver's copy and its watches
+        const CLAUSE_SIZE: usize = 64;
+        // per literal: assignment, watch lists, heap and term mapping
+        const LIT_SIZE: usize = 96;
+        std::mem::size_of::<Self>()
+            + self.api_n_clauses() * CLAUSE_SIZE
+            + self.clauses.len() * std::mem::size_of::<Vec<SatLit>>()
+            + self.n_clause_lits * std::mem::size_of::<SatLit>()
+            + self.api_n_lits() * LIT_SIZE
+            + self.lits.len() * std::mem::size_of::<LitDecl>()
     }
 
+    /// Add the clause `lits` to the SAT solver.
+    ///
+    /// The current clause (see `api_add_clause_lit`) is not affected.
+    /// Fails if one of the literals is not a literal of this solver.
+    pub fn api_add_clause_from(&mut self, lits: &[Lit]) -> Result<()> {
+        for l in lits.iter() { self.check_lit(l.to_int())?; }
+        let mut c = lits.iter().map(|l| l.0).collect();
+        self.add_clause_buf(&mut c);
+        Ok(())
+    }
+
+    /// Add several clauses, given as literals (in the `to_int` form)
+    /// each followed by `0`. The last `0` can be omitted.
+    ///
+    /// Fails, without adding any clause, if one of the literals is not
+    /// a literal of this solver.
+    pub fn api_add_clauses_zero_sep(&mut self, lits: &[i32]) -> Result<()> {
+        for &x in lits.iter().filter(|&&x| x != 0) { self.check_lit(x)?; }
+        let mut c = vec![];
+        for &x in lits.iter() {
+            if x == 0 {
+                self.add_clause_buf(&mut c);
+            } else {
+                c.push(Lit::unsafe_from_int(x).0);
+            }
+        }
+        if c.len() > 0 {
+            self.add_clause_buf(&mut c);
+        }
+        Ok(())
+    }
+
+    /// Literals created so far, in order.
+    pub fn lit_decls(&self) -> &[LitDecl] { &self.lits }
+
+    /// Clauses added so far, in order.
+    ///
+    /// Fails unless `keep_clauses` was set in the options when each of
+    /// them was added.
+    pub fn clauses(&self) -> Result<&[Vec<SatLit>]> {
+        if self.all_clauses_kept { Ok(&self.clauses) } else { Err(Error::ClausesNotKept) }
+    }
+
+    /// Assumptions used by the last call to `api_solve`.
+    pub fn last_assumptions(&self) -> &[SatLit] { &self.last_assumptions }
+
     pub fn api_n_lits(&self) -> usize { self.s.n_lits() }
     pub fn api_n_props(&self) -> usize { self.s.n_props() }
     pub fn api_n_clauses(&self) -> usize { self.s.n_clauses() }
     pub fn api_n_conflicts(&self) -> usize { self.s.n_conflicts() }
     pub fn api_n_decisions(&self) -> usize { self.s.n_decisions() }
+
+    /// All the statistics at once.
+    pub fn api_stats(&self) -> Stats {
+        let t = self.solve_time;
+        Stats{
+            n_lits: self.api_n_lits(),
+            n_clauses: self.api_n_clauses(),
+            n_decisions: self.api_n_decisions(),
+            n_props: self.api_n_props(),
+            n_conflicts: self.api_n_conflicts(),
+            n_solve: self.n_solve,
+            solve_time: t.as_secs() as f64 + t.subsec_nanos() as f64 * 1e-9,
+        }
+    }
+}
+
+/// Peak resident memory of the whole process, in bytes.
+///
+/// It is read from `/proc` (`VmHWM`), and is `None` where that is not
+/// available.
+pub fn peak_mem() -> Option<usize> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    // `VmHWM:     1234 kB`
+    let kb: usize = line["VmHWM:".len() ..].trim().trim_end_matches("kB").trim().parse().ok()?;
+    Some(kb * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solver(ctx: &mut Ctx) -> Solver {
+        Solver::new_with(ctx, Options{keep_clauses: true, ..Options::default()})
+    }
+
+    fn ints(s: &Solver) -> Vec<Vec<i32>> {
+        s.clauses().unwrap().iter()
+            .map(|c| c.iter().map(|&l| Lit::new(l).to_int()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn batch_vs_one_at_a_time() {
+        let mut ctx = Ctx::new();
+        let mut s1 = solver(&mut ctx);
+        let mut s2 = solver(&mut ctx);
+        let mut s3 = solver(&mut ctx);
+        let v1: Vec<i32> = (0 .. 3).map(|_| s1.api_make_lit().to_int()).collect();
+        for _ in 0 .. 3 {
+            s2.api_make_lit();
+            s3.api_make_lit();
+        }
+        let (a, b, c) = (v1[0], v1[1], v1[2]);
+        let clauses = vec![vec![a, -b], vec![b, c], vec![-a, -c]];
+
+        for cl in clauses.iter() {
+            for &x in cl.iter() { s1.api_add_clause_lit(Lit::unsafe_from_int(x)) }
+            s1.api_add_clause();
+        }
+        for cl in clauses.iter() {
+            let lits: Vec<Lit> = cl.iter().map(|&x| Lit::unsafe_from_int(x)).collect();
+            s2.api_add_clause_from(&lits).unwrap();
+        }
+        // last `0` omitted
+        s3.api_add_clauses_zero_sep(&[a, -b, 0, b, c, 0, -a, -c]).unwrap();
+
+        assert_eq!(ints(&s1), clauses);
+        assert_eq!(ints(&s2), clauses);
+        assert_eq!(ints(&s3), clauses);
+        let r: Vec<bool> = [&mut s1, &mut s2, &mut s3].iter_mut().map(|s| s.api_solve(&mut ctx)).collect();
+        assert_eq!(r, vec![true, true, true]);
+    }
+
+    #[test]
+    fn unknown_lits() {
+        let mut ctx = Ctx::new();
+        let mut s = solver(&mut ctx);
+        let a = s.api_make_lit();
+        let n = s.api_n_lits() as i32;
+        for &x in [0, n+1, -(n+1), i32::MIN, i32::MAX].iter() {
+            assert_eq!(s.check_lit(x).err(), Some(Error::UnknownLit(x)));
+            assert!(s.api_add_clause_from(&[a, Lit::unsafe_from_int(x)]).is_err());
+            // nothing is added, even the valid clause before the bad one
+            assert!(s.api_add_clauses_zero_sep(&[a.to_int(), 0, x, 0]).is_err());
+        }
+        assert_eq!(ints(&s).len(), 0);
+        assert!(s.check_lit(-a.to_int()).is_ok());
+    }
+
+    #[test]
+    fn current_clause_untouched() {
+        let mut ctx = Ctx::new();
+        let mut s = solver(&mut ctx);
+        let (a, b) = (s.api_make_lit(), s.api_make_lit());
+        s.api_add_clause_lit(a);
+        s.api_add_clause_from(&[b]).unwrap();
+        s.api_add_clause();
+        assert_eq!(ints(&s), vec![vec![b.to_int()], vec![a.to_int()]]);
+    }
+
+    #[test]
+    fn term_lits_and_model_bits() {
+        let mut ctx = Ctx::new();
+        let bool_ = ctx.api_ty_bool();
+        let ts: Vec<AST> = ["p", "q", "r"].iter()
+            .map(|n| ctx.api_const(n, &[], bool_).unwrap()).collect();
+        let not_q = ctx.api_not(ts[1]);
+        let ts = vec![ts[0], not_q, ts[2], ts[0]];
+        let mut s1 = solver(&mut ctx);
+        let mut s2 = solver(&mut ctx);
+        let l1: Vec<i32> = ts.iter().map(|&t| s1.api_make_term_lit(&mut ctx, t).to_int()).collect();
+        let l2: Vec<i32> = s2.api_make_term_lits(&mut ctx, &ts).iter().map(|l| l.to_int()).collect();
+        assert_eq!(l1, l2);
+        assert_eq!(l1[0], l1[3]);
+
+        s1.api_add_clause_from(&[Lit::unsafe_from_int(l1[0])]).unwrap();
+        s1.api_add_clause_from(&[Lit::unsafe_from_int(l1[1])]).unwrap();
+        assert!(s1.api_solve(&mut ctx));
+        let bits = s1.api_model_bits();
+        assert_eq!(bits.len(), s1.api_n_lits() / 8 + 1);
+        for v in 1 ..= s1.api_n_lits() {
+            let val = s1.api_value(Lit::unsafe_from_int(v as i32)) == Lbool::TRUE;
+            assert_eq!(bits[v / 8] & (1 << (v % 8)) != 0, val);
+        }
+        assert_eq!(bits[0] & 1, 0); // no variable 0
+    }
+
+    #[test]
+    fn new_proved_lvl_0() {
+        let mut ctx = Ctx::new();
+        let mut s = solver(&mut ctx);
+        let (a, b, c) = (s.api_make_lit(), s.api_make_lit(), s.api_make_lit());
+        let neg = |lit: Lit| Lit::unsafe_from_int(-lit.to_int());
+        let proved = |s: &mut Solver| -> Vec<i32> {
+            let mut v: Vec<i32> = s.api_new_proved_at_lvl_0().iter().map(|&l| Lit::new(l).to_int()).collect();
+            v.sort();
+            v
+        };
+        s.api_add_clause_from(&[a]).unwrap();
+        s.api_add_clause_from(&[neg(a), b]).unwrap();
+        assert!(s.api_solve(&mut ctx));
+        assert_eq!(proved(&mut s), vec![a.to_int(), b.to_int()]);
+        assert_eq!(proved(&mut s), vec![]);
+        // only the literals proved since the last call
+        s.api_add_clause_from(&[neg(c)]).unwrap();
+        assert!(s.api_solve(&mut ctx));
+        assert_eq!(proved(&mut s), vec![-c.to_int()]);
+        assert_eq!(s.api_proved_at_lvl_0().len(), 3);
+    }
 }
@@ -0,0 +1,1138 @@
+//! OCaml stubs for `Batsmt`.
+
+use {
+    std::{ptr, mem, convert::TryFrom, panic::{self, AssertUnwindSafe}, time::Duration},
+    batsmt_core::ast_u32::{self,AST},
+    ocaml::{ToValue,Value,value,Str,Array,Tuple},
+    crate::{
+        ctx::{Ctx, AstKind},
+        solver::{Solver, Lit, Lbool, Options, peak_mem},
+        custom::{self, CustomOperations, Mem},
+        busy::Busy,
+        error, job, portfolio, smtlib, printer, dimacs, trace, replay,
+    },
+};
+
+type Job = job::Job;
+
+#[inline]
+fn lit_of_value(lit: Value) -> Lit {
+    Lit::unsafe_from_int(lit.isize_val() as i32)
+}
+
+#[inline]
+fn value_of_lit(lit: Lit) -> Value {
+    Value::isize(Lit::to_int(&lit) as isize)
+}
+
+// term of `ctx` in `t`, or raise `Failure` if `ctx` has no such term
+#[inline]
+unsafe fn ast_of_value(ctx: &Ctx, t: Value) -> AST {
+    let i = u32::try_from(t.isize_val()).unwrap_or(u32::MAX);
+    ok_or_fail(ctx.check_term(ast_u32::manager_util::ast_from_u32(i)))
+}
+
+#[inline]
+fn value_of_ast(t: AST) -> Value {
+    Value::isize(t.idx() as isize)
+}
+
+
+#[inline]
+fn int_of_lbool(r: Lbool) -> isize {
+    if r == Lbool::TRUE { 0 }
+    else if r == Lbool::FALSE { 1 }
+    else { 2 }
+}
+
+// record a call, if tracing is enabled. `trace!(name, [args], res)`
+macro_rules! trace {
+    ($name:expr, [$($arg:expr),*], $res:expr) => {
+        if trace::enabled() { trace::record($name, &[$($arg),*], $res) }
+    }
+}
+
+#[inline]
+fn tr_ctx(v: Value) -> trace::Arg<'static> {
+    trace::Arg::Ctx(unsafe { (*v.custom_ptr_val::<CtxBlock>()).handle })
+}
+
+#[inline]
+fn tr_solver(v: Value) -> trace::Arg<'static> {
+    trace::Arg::Solver(unsafe { (*v.custom_ptr_val::<SolverBlock>()).handle })
+}
+
+#[inline]
+fn tr_job(v: Value) -> trace::Arg<'static> {
+    trace::Arg::Job(unsafe { (*v.custom_ptr_val::<JobBlock>()).handle })
+}
+
+#[inline]
+fn tr_lit(lit: Lit) -> trace::Arg<'static> { trace::Arg::Int(lit.to_int() as i64) }
+
+#[inline]
+fn tr_ast(t: AST) -> trace::Arg<'static> { trace::Arg::Int(t.idx() as i64) }
+
+#[inline]
+fn tr_lits(lits: &[Lit]) -> trace::Arg<'static> {
+    trace::Arg::Ints(lits.iter().map(|l| l.to_int() as i64).collect())
+}
+
+// NOTE on storage:
+// we use an OCaml custom block to store the pointer to the Solver (not the
+// solver itself). Similarly for the context.
+// The pointer comes first, so that blocks can be read as a `*mut Solver`
+// (resp. `*mut Ctx`) too. The blocks also keep track of the memory they
+// own, as reported to the GC, and of their handle in traces.
+
+#[repr(C)]
+struct CtxBlock {
+    ctx: *mut Ctx,
+    mem: Mem,
+    handle: usize,
+}
+
+// A solver's custom block also holds a generational global root on the
+// OCaml value of its context, so that the context is not collected while
+// the solver is still reachable.
+#[repr(C)]
+struct SolverBlock {
+    s: *mut Solver,
+    ctx: *mut ocaml::core::Value, // boxed root, or null
+    mem: Mem,
+    handle: usize,
+}
+
+// A job's custom block holds a generational global root on the OCaml
+// value of its solver until the search is over, see `job_over`.
+#[repr(C)]
+struct JobBlock {
+    job: *mut Job,
+    solver: *mut ocaml::core::Value, // boxed root, or null
+    handle: usize,
+}
+
+static CTX_OPS: CustomOperations = custom_ops!("batsmt.ctx", batsmt_ctx_finalizer);
+static SOLVER_OPS: CustomOperations = custom_ops!("batsmt.solver", batsmt_solver_finalizer);
+static JOB_OPS: CustomOperations = custom_ops!("batsmt.job", batsmt_job_finalizer);
+
+extern "C" {
+    fn caml_register_generational_global_root(v: *mut ocaml::core::Value);
+    fn caml_remove_generational_global_root(v: *mut ocaml::core::Value);
+    fn caml_alloc_initialized_string(len: usize, p: *const u8) -> ocaml::core::Value;
+    fn caml_invalid_argument(msg: *const u8) -> !;
+}
+
+// release the root on the solver's context, if any
+unsafe fn release_ctx_root(v: Value) {
+    let b = v.custom_ptr_val_mut::<SolverBlock>();
+    if ! (*b).ctx.is_null() {
+        caml_remove_generational_global_root((*b).ctx);
+        drop(Box::from_raw((*b).ctx));
+        (*b).ctx = ptr::null_mut();
+    }
+}
+
+// macro to locally borrow context. `with_ctx!(s, v, block)`
+// runs `block` in a context where `ctx` binds to a `&mut ctx` from `v`
+macro_rules! with_ctx {
+    ($s: ident, $v:expr, $code:block) => {
+        {
+            let p = *$v.custom_ptr_val::<*mut Ctx>();
+            if p.is_null() { fail_deleted() }
+            // check before borrowing: a search might hold a `&mut` to it
+            if Ctx::busy_of(p).is_busy() { fail_busy() }
+            let $s : &mut Ctx = &mut *p;
+            $code;
+            (*$v.custom_ptr_val_mut::<CtxBlock>()).mem.grow($s.mem_size());
+        };
+    }
+}
+
+// macro to locally borrow solver. `with_solver!(s, v, block)`
+// runs `block` in a context where `s` binds to a `&mut solver` from `v`
+macro_rules! with_solver {
+    ($s: ident, $v:expr, $code:block) => {
+        {
+            let p = *$v.custom_ptr_val::<*mut Solver>();
+            if p.is_null() { fail_deleted() }
+            // check before borrowing: a search might hold a `&mut` to it
+            if Solver::busy_of(p).is_busy() { fail_busy() }
+            let $s : &mut Solver = &mut *p;
+            $code;
+            (*$v.custom_ptr_val_mut::<SolverBlock>()).mem.grow($s.mem_size());
+        };
+    }
+}
+
+// unwrap `r`, or raise `Failure` with the error
+unsafe fn ok_or_fail<T>(r: error::Result<T>) -> T {
+    match r {
+        Ok(x) => x,
+        Err(e) => {
+            ocaml::runtime::failwith(&format!("batsmt: {}", e));
+            unreachable!()
+        },
+    }
+}
+
+// raise `Failure` because a value is used by a search running in
+// another thread.
+unsafe fn fail_busy() {
+    ocaml::runtime::failwith("batsmt: value is in use by a running search");
+}
+
+// raise `Failure` because a value was deleted explicitly.
+unsafe fn fail_deleted() {
+    ocaml::runtime::failwith("batsmt: value was deleted");
+}
+
+// raise `Failure` unless `s` was created with `ctx`
+unsafe fn check_ctx(s: &Solver, ctx: &Ctx) { ok_or_fail(s.check_ctx(ctx)) }
+
+fn delete_value<T>(v: Value) {
+    if unsafe{ *v.custom_ptr_val::<*const T>() } != ptr::null() {
+        //println!("delete value");
+        let s = unsafe { Box::from_raw(*v.custom_ptr_val_mut::<*mut T>()) };
+        mem::drop(s); // delete!
+    }
+    // be sure not to delete twice
+    unsafe { * v.custom_ptr_val_mut::<*const Solver>() = ptr::null() };
+}
+
+extern "C" fn batsmt_ctx_finalizer(v: ocaml::core::Value) {
+    let v = Value::new(v);
+    unsafe { (*v.custom_ptr_val_mut::<CtxBlock>()).mem.release() };
+    let p = unsafe { *v.custom_ptr_val::<*const Ctx>() };
+    // a background search still uses it, and will free it once done
+    if !p.is_null() && unsafe { Ctx::busy_of(p).orphan() } { return }
+    delete_value::<Ctx>(v);
+}
+
+// finalizer for values
+extern "C" fn batsmt_solver_finalizer(v: ocaml::core::Value) {
+    let v = Value::new(v);
+    unsafe {
+        release_ctx_root(v);
+        (*v.custom_ptr_val_mut::<SolverBlock>()).mem.release();
+    }
+    let p = unsafe { *v.custom_ptr_val::<*const Solver>() };
+    if !p.is_null() && unsafe { Solver::busy_of(p).orphan() } { return }
+    delete_value::<Solver>(v);
+}
+
+extern "C" fn batsmt_job_finalizer(v: ocaml::core::Value) {
+    let v = Value::new(v);
+    unsafe { release_solver_root(v) };
+    delete_value::<Job>(v);
+}
+
+caml!(ml_batsmt_ctx_new, |_params|, <res>, {
+    trace::init_from_env();
+    let ctx = Box::new(Ctx::new());
+    let ptr = Box::into_raw(ctx) as *mut Ctx;
+    let handle = trace::new_handle();
+    trace!("ctx_new", [], trace::Arg::Ctx(handle));
+    let mem = (*ptr).mem_size();
+    res = custom::alloc(&CTX_OPS, CtxBlock{ctx: ptr, mem: Mem::new(mem), handle}, mem);
+} -> res);
+
+caml!(ml_batsmt_ctx_compact, |ptr, roots|, <res, new_ctx, terms>, {
+    with_ctx!(ctx, ptr, {
+        let roots: Array = roots.into();
+        let roots: Vec<AST> = (0 .. roots.len()).map(|i| ast_of_value(ctx, roots.get(i).unwrap())).collect();
+        let (c, ts) = ctx.api_compact(&roots);
+        let p = Box::into_raw(Box::new(c));
+        let handle = trace::new_handle();
+        trace!("ctx_compact",
+            [tr_ctx(ptr), trace::Arg::Ctx(handle),
+             trace::Arg::Ints(roots.iter().map(|t| t.idx() as i64).collect())],
+            trace::Arg::Ints(ts.iter().map(|t| t.idx() as i64).collect()));
+        let mem = (*p).mem_size();
+        new_ctx = custom::alloc(&CTX_OPS, CtxBlock{ctx: p, mem: Mem::new(mem), handle}, mem);
+        terms = ts.iter().map(|&t| value_of_ast(t)).collect::<Vec<_>>().to_value();
+        let mut tup = Tuple::new(2);
+        let _ = tup.set(0, new_ctx);
+        let _ = tup.set(1, terms);
+        res = tup.into();
+    })
+} -> res);
+
+caml!(ml_batsmt_ctx_delete, |param|, <res>, {
+    let p = *param.custom_ptr_val::<*const Ctx>();
+    if !p.is_null() && Ctx::busy_of(p).is_busy() { fail_busy() }
+    trace!("ctx_delete", [tr_ctx(param)], trace::Arg::Unit);
+    (*param.custom_ptr_val_mut::<CtxBlock>()).mem.release();
+    delete_value::<Ctx>(param);
+    res = value::UNIT;
+} -> res);
+
+#[inline]
+fn options_of_values(seed: Value, auto_simplify: Value, keep_clauses: Value) -> Options {
+    Options{
+        seed: seed.isize_val() as u64,
+        auto_simplify: auto_simplify.isize_val() != 0,
+        keep_clauses: keep_clauses.isize_val() != 0,
+    }
+}
+
+// trace arguments for `opts`, see `options_of_values`
+fn tr_options(opts: &Options) -> [trace::Arg<'static>; 3] {
+    [trace::Arg::Int(opts.seed as i64), trace::Arg::Bool(opts.auto_simplify),
+     trace::Arg::Bool(opts.keep_clauses)]
+}
+
+caml!(ml_batsmt_solver_new, |ptr, seed, auto_simplify, keep_clauses|, <res>, {
+    with_ctx!(ctx, ptr, {
+        let opts = options_of_values(seed, auto_simplify, keep_clauses);
+        let solver = Box::new(Solver::new_with(ctx, opts));
+        let p = Box::into_raw(solver) as *mut Solver;
+        let root = Box::into_raw(Box::new(ptr.0));
+        caml_register_generational_global_root(root);
+        let handle = trace::new_handle();
+        if trace::enabled() {
+            let [a1, a2, a3] = tr_options(&opts);
+            trace!("solver_new", [tr_ctx(ptr), a1, a2, a3], trace::Arg::Solver(handle));
+        }
+        let mem = (*p).mem_size();
+        let b = SolverBlock{s: p, ctx: root, mem: Mem::new(mem), handle};
+        res = custom::alloc(&SOLVER_OPS, b, mem);
+    })
+} -> res);
+
+caml!(ml_batsmt_solver_delete, |param|, <res>, {
+    let p = *param.custom_ptr_val::<*const Solver>();
+    if !p.is_null() && Solver::busy_of(p).is_busy() { fail_busy() }
+    trace!("solver_delete", [tr_solver(param)], trace::Arg::Unit);
+    release_ctx_root(param);
+    (*param.custom_ptr_val_mut::<SolverBlock>()).mem.release();
+    delete_value::<Solver>(param);
+    res = value::UNIT;
+} -> res);
+
+caml!(ml_batsmt_solver_set_options, |ptr, seed, auto_simplify, keep_clauses|, <res>, {
+    with_solver!(solver, ptr, {
+        let opts = options_of_values(seed, auto_simplify, keep_clauses);
+        solver.api_set_options(opts);
+        let [a1, a2, a3] = tr_options(&opts);
+        trace!("solver_set_options", [tr_solver(ptr), a1, a2, a3], trace::Arg::Unit);
+        res = value::UNIT;
+    })
+} -> res);
+
+caml!(ml_batsmt_solver_new_lit, |ptr|, <res>, {
+    with_solver!(solver, ptr, {
+        let lit = solver.api_make_lit();
+        trace!("solver_new_lit", [tr_solver(ptr)], tr_lit(lit));
+        res = value_of_lit(lit);
+    })
+} -> res);
+
+caml!(ml_batsmt_solver_new_term_lit, |ptr, ptr_c, t|, <res>, {
+    with_solver!(solver, ptr, {
+        with_ctx!(ctx, ptr_c, {
+            check_ctx(solver, ctx);
+            let t = ast_of_value(ctx, t);
+            let lit = solver.api_make_term_lit(ctx, t);
+            trace!("solver_new_term_lit", [tr_solver(ptr), tr_ctx(ptr_c), tr_ast(t)], tr_lit(lit));
+            res = value_of_lit(lit);
+        })
+    })
+} -> res);
+
+caml!(ml_batsmt_solver_new_term_lits, |ptr, ptr_c, ts|, <res>, {
+    with_solver!(solver, ptr, {
+        with_ctx!(ctx, ptr_c, {
+            check_ctx(solver, ctx);
+            let ts: Array = ts.into();
+            let ts: Vec<AST> = (0 .. ts.len()).map(|i| ast_of_value(ctx, ts.get(i).unwrap())).collect();
+            let lits = solver.api_make_term_lits(ctx, &ts);
+            trace!("solver_new_term_lits",
+                [tr_solver(ptr), tr_ctx(ptr_c),
+                 trace::Arg::Ints(ts.iter().map(|t| t.idx() as i64).collect())],
+                tr_lits(&lits));
+            res = lits.iter().map(|&lit| value_of_lit(lit)).collect::<Vec<_>>().to_value();
+        })
+    })
+} -> res);
+
+/// Add literal
+caml!(ml_batsmt_solver_add_clause_lit, |ptr, lit|, <res>, {
+    with_solver!(solver, ptr, {
+        let lit = lit_of_value(lit);
+        solver.api_add_clause_lit(lit);
+        trace!("solver_add_clause_lit", [tr_solver(ptr), tr_lit(lit)], trace::Arg::Unit);
+        res = value::UNIT;
+    })
+} -> res);
+
+/// Add clause
+caml!(ml_batsmt_solver_add_clause, |ptr|, <res>, {
+    with_solver!(solver, ptr, {
+        solver.api_add_clause();
+        trace!("solver_add_clause", [tr_solver(ptr)], trace::Arg::Unit);
+        res = value::UNIT;
+    })
+} -> res);
+
+/// Add a clause given as an array of literals
+caml!(ml_batsmt_solver_add_clause_a, |ptr, lits|, <res>, {
+    with_solver!(solver, ptr, {
+        let lits: Array = lits.into();
+        let lits: Vec<Lit> = (0 .. lits.len()).map(|i| lit_of_value(lits.get(i).unwrap())).collect();
+        ok_or_fail(solver.api_add_clause_from(&lits));
+        trace!("solver_add_clause_a", [tr_solver(ptr), tr_lits(&lits)], trace::Arg::Unit);
+        res = value::UNIT;
+    })
+} -> res);
+
+// header of a bigarray (`struct caml_ba_array`), followed by its dimensions
+#[repr(C)]
+struct BigArray {
+    data: *mut std::os::raw::c_void,
+    num_dims: isize,
+    flags: isize,
+    proxy: *mut std::os::raw::c_void,
+    dim: [isize; 1],
+}
+
+/// Add clauses given as a bigarray of int32 literals, each clause followed by `0`
+caml!(ml_batsmt_solver_add_clauses, |ptr, lits|, <res>, {
+    with_solver!(solver, ptr, {
+        let ba = &*lits.custom_ptr_val::<BigArray>();
+        let lits: &[i32] =
+            if ba.dim[0] == 0 { &[] }
+            else { std::slice::from_raw_parts(ba.data as *const i32, ba.dim[0] as usize) };
+        ok_or_fail(solver.api_add_clauses_zero_sep(lits));
+        trace!("solver_add_clauses", [tr_solver(ptr),
+            trace::Arg::Ints(lits.iter().map(|&x| x as i64).collect())], trace::Arg::Unit);
+        res = value::UNIT;
+    })
+} -> res);
+
+/// Add assumption
+caml!(ml_batsmt_solver_add_assumption, |ptr, lit|, <res>, {
+    with_solver!(solver, ptr, {
+        let lit = lit_of_value(lit);
+        solver.api_add_assumption(lit);
+        trace!("solver_add_assumption", [tr_solver(ptr), tr_lit(lit)], trace::Arg::Unit);
+        res = value::UNIT;
+    })
+} -> res);
+
+// Releases a busy flag when dropped.
+struct BusyGuard(*const Busy);
+
+impl Drop for BusyGuard {
+    fn drop(&mut self) { unsafe { (*self.0).release(); } }
+}
+
+// Run `f` without holding the OCaml runtime lock, so other OCaml threads
+// can run meanwhile, with the `flags` marked busy until it returns.
+// Raises `Failure` if a flag is already taken, or if `f` panics; in both
+// cases the flags are released and the lock is held again before raising
+// (`failwith` does not run destructors).
+//
+// NOTE: the custom blocks only contain pointers to the Rust heap, so it's
+// fine if the GC moves them while the lock is released.
+unsafe fn without_lock<T>(flags: &[*const Busy], f: impl FnOnce() -> T) -> T {
+    let mut guards = Vec::with_capacity(flags.len());
+    for &b in flags.iter() {
+        if ! (*b).acquire() {
+            drop(guards);
+            fail_busy();
+            unreachable!()
+        }
+        guards.push(BusyGuard(b));
+    }
+    ocaml::runtime::release_lock();
+    let r = panic::catch_unwind(AssertUnwindSafe(f));
+    ocaml::runtime::acquire_lock();
+    drop(guards);
+    match r {
+        Ok(x) => x,
+        Err(_) => {
+            ocaml::runtime::failwith("batsmt: the search panicked");
+            unreachable!()
+        },
+    }
+}
+
+// Solve without the runtime lock, see `without_lock`.
+unsafe fn solve_blocking(solver: &mut Solver, ctx: &mut Ctx) -> bool {
+    let flags = [ctx.busy() as *const Busy, solver.busy() as *const Busy];
+    without_lock(&flags, || solver.api_solve(ctx))
+}
+
+caml!(ml_batsmt_solver_solve, |ptr_s, ptr_ctx|, <res>, {
+    with_ctx!(ctx, ptr_ctx, {
+        with_solver!(solver, ptr_s, {
+            check_ctx(solver, ctx);
+            let r = solve_blocking(solver, ctx);
+            //println!("res: {:?}, model: {:?}", r, solver.get_model());
+            trace!("solver_solve", [tr_solver(ptr_s), tr_ctx(ptr_ctx)], trace::Arg::Bool(r));
+            res = Value::bool(r);
+        });
+    })
+} -> res);
+
+#[inline]
+fn int_of_status(st: job::Status) -> isize {
+    match st {
+        job::Status::Running => 0,
+        job::Status::Done(true) => 1,
+        job::Status::Done(false) => 2,
+        job::Status::Detached => 3,
+        job::Status::Failed => 4,
+    }
+}
+
+// get the job from its custom block
+unsafe fn job_of_value<'a>(v: Value) -> &'a Job {
+    assert!(! v.custom_ptr_val::<* const Job>().is_null());
+    &**v.custom_ptr_val::<*const Job>()
+}
+
+caml!(ml_batsmt_solver_solve_async, |ptr_s, ptr_ctx|, <res>, {
+    with_ctx!(ctx, ptr_ctx, {
+        with_solver!(solver, ptr_s, {
+            check_ctx(solver, ctx);
+            if ! ctx.busy().acquire() { fail_busy() }
+            if ! solver.busy().acquire() {
+                ctx.busy().release();
+                fail_busy()
+            }
+            let job = Box::new(Job::spawn(solver, ctx));
+            let ptr = Box::into_raw(job) as *mut Job;
+            let root = Box::into_raw(Box::new(ptr_s.0));
+            caml_register_generational_global_root(root);
+            let handle = trace::new_handle();
+            trace!("solver_solve_async", [tr_solver(ptr_s), tr_ctx(ptr_ctx)],
+                trace::Arg::Job(handle));
+            let b = JobBlock{job: ptr, solver: root, handle};
+            res = custom::alloc(&JOB_OPS, b, mem::size_of::<Job>());
+        });
+    })
+} -> res);
+
+// release the root on the job's solver, if any
+unsafe fn release_solver_root(v: Value) {
+    let b = v.custom_ptr_val_mut::<JobBlock>();
+    if ! (*b).solver.is_null() {
+        caml_remove_generational_global_root((*b).solver);
+        drop(Box::from_raw((*b).solver));
+        (*b).solver = ptr::null_mut();
+    }
+}
+
+// once the search is over, report to the GC the memory the solver
+// grew by, and release the solver
+unsafe fn job_over(v: Value, st: job::Status) {
+    match st {
+        job::Status::Done(_) | job::Status::Failed => (),
+        job::Status::Running | job::Status::Detached => return,
+    }
+    let b = v.custom_ptr_val::<JobBlock>();
+    if (*b).solver.is_null() { return } // already done
+    let sv = Value::new(*(*b).solver);
+    let p = *sv.custom_ptr_val::<*const Solver>();
+    // it might have been deleted, or be searching again
+    if ! p.is_null() && ! Solver::busy_of(p).is_busy() {
+        (*sv.custom_ptr_val_mut::<SolverBlock>()).mem.grow((*p).mem_size());
+    }
+    release_solver_root(v);
+}
+
+caml!(ml_batsmt_job_poll, |ptr|, <res>, {
+    let job = job_of_value(ptr);
+    let st = job.poll();
+    job_over(ptr, st);
+    let st = int_of_status(st);
+    trace!("job_poll", [tr_job(ptr)], trace::Arg::Int(st as i64));
+    res = Value::isize(st);
+} -> res);
+
+/// Wait for the job; a negative timeout means no timeout.
+caml!(ml_batsmt_job_wait, |ptr, timeout|, <res>, {
+    let job = job_of_value(ptr);
+    let secs = timeout.f64_val();
+    let timeout = if secs < 0. { None } else { Some(Duration::from_secs_f64(secs)) };
+    ocaml::runtime::release_lock();
+    let st = job.wait(timeout);
+    ocaml::runtime::acquire_lock();
+    job_over(ptr, st);
+    let st = int_of_status(st);
+    trace!("job_wait", [tr_job(ptr), trace::Arg::Float(secs)], trace::Arg::Int(st as i64));
+    res = Value::isize(st);
+} -> res);
+
+caml!(ml_batsmt_job_detach, |ptr|, <res>, {
+    let job = job_of_value(ptr);
+    job.detach();
+    trace!("job_detach", [tr_job(ptr)], trace::Arg::Unit);
+    res = value::UNIT;
+} -> res);
+
+/// Solve copies of the problem in parallel, one per seed, and return
+/// `(is_sat, winner, unsat_core)` for the first one to answer.
+caml!(ml_batsmt_solver_solve_portfolio, |ptr_s, ptr_ctx, seeds|, <res, core>, {
+    with_ctx!(ctx, ptr_ctx, {
+        with_solver!(solver, ptr_s, {
+            check_ctx(solver, ctx);
+            let seeds: Array = seeds.into();
+            let seeds: Vec<u64> =
+                (0 .. seeds.len())
+                .map(|i| seeds.get(i).unwrap().isize_val() as u64)
+                .collect();
+            let tr_seeds = trace::Arg::Ints(seeds.iter().map(|&x| x as i64).collect());
+            ok_or_fail(solver.clauses().map(|_| ())); // keep the assumptions if it fails
+            let assumptions = solver.api_take_assumptions();
+            let p = ok_or_fail(portfolio::Problem::new(solver, ctx, &assumptions));
+            // the workers only use their own copy of the problem
+            let ans = without_lock(&[], || portfolio::solve(p, &seeds));
+            match ans {
+                None => {
+                    ocaml::runtime::failwith("batsmt: every portfolio worker failed");
+                    res = value::UNIT;
+                },
+                Some(ans) => {
+                    trace!("solver_solve_portfolio", [tr_solver(ptr_s), tr_ctx(ptr_ctx), tr_seeds],
+                        trace::Arg::Bool(ans.sat));
+                    core =
+                        ans.core.iter()
+                        .map(|&lit| value_of_lit(lit))
+                        .collect::<Vec<_>>()
+                        .to_value();
+                    let mut tup = Tuple::new(3);
+                    let _ = tup.set(0, Value::bool(ans.sat));
+                    let _ = tup.set(1, Value::isize(ans.winner as isize));
+                    let _ = tup.set(2, core);
+                    res = tup.into();
+                },
+            }
+        });
+    })
+} -> res);
+
+caml!(ml_batsmt_solver_simplify, |ptr_s|, <res>, {
+    with_solver!(solver, ptr_s, {
+        let r = solver.api_simplify();
+        trace!("solver_simplify", [tr_solver(ptr_s)], trace::Arg::Bool(r));
+        res = Value::bool(r);
+    });
+} -> res);
+
+caml!(ml_batsmt_solver_unsat_core, |ptr|, <res>, {
+    with_solver!(solver, ptr, {
+        let core: Vec<Lit> = solver.api_unsat_core().iter().map(|&lit| Lit::new(lit)).collect();
+        trace!("solver_unsat_core", [tr_solver(ptr)], tr_lits(&core));
+        let core = core.iter().map(|&lit| value_of_lit(lit)).collect::<Vec<_>>();
+        res = core.to_value();
+    })
+} -> res);
+
+caml!(ml_batsmt_solver_unsat_core_contains, |ptr, lit|, <res>, {
+    with_solver!(solver, ptr, {
+        let lit = lit_of_value(lit);
+        let r = solver.api_unsat_core_contains(lit);
+        trace!("solver_unsat_core_contains", [tr_solver(ptr), tr_lit(lit)], trace::Arg::Bool(r));
+        res = Value::bool(r);
+    })
+} -> res);
+
+caml!(ml_batsmt_nclauses, |ptr|, <res>, {
+    with_solver!(solver, ptr, {
+        let n = solver.api_n_clauses();
+        trace!("nclauses", [tr_solver(ptr)], trace::Arg::Int(n as i64));
+        res = Value::isize(n as isize);
+    })
+} -> res);
+
+caml!(ml_batsmt_nlits, |ptr|, <res>, {
+    with_solver!(solver, ptr, {
+        let n = solver.api_n_lits();
+        trace!("nlits", [tr_solver(ptr)], trace::Arg::Int(n as i64));
+        res = Value::isize(n as isize);
+    })
+} -> res);
+
+caml!(ml_batsmt_ndecisions, |ptr|, <res>, {
+    with_solver!(solver, ptr, {
+        let n = solver.api_n_decisions();
+        trace!("ndecisions", [tr_solver(ptr)], trace::Arg::Int(n as i64));
+        res = Value::isize(n as isize);
+    })
+} -> res);
+
+caml!(ml_batsmt_nprops, |ptr|, <res>, {
+    with_solver!(solver, ptr, {
+        let n = solver.api_n_props();
+        trace!("nprops", [tr_solver(ptr)], trace::Arg::Int(n as i64));
+        res = Value::isize(n as isize);
+    })
+} -> res);
+
+caml!(ml_batsmt_nconflicts, |ptr|, <res>, {
+    with_solver!(solver, ptr, {
+        let n = solver.api_n_conflicts();
+        trace!("nconflicts", [tr_solver(ptr)], trace::Arg::Int(n as i64));
+        res = Value::isize(n as isize);
+    })
+} -> res);
+
+caml!(ml_batsmt_solver_stats, |ptr|, <res, time>, {
+    with_solver!(solver, ptr, {
+        let st = solver.api_stats();
+        trace!("solver_stats", [tr_solver(ptr)], trace::Arg::Unit);
+        time = st.solve_time.to_value();
+        // same layout as `Solver.stats`
+        let mut tup = Tuple::new(7);
+        let _ = tup.set(0, Value::isize(st.n_lits as isize));
+        let _ = tup.set(1, Value::isize(st.n_clauses as isize));
+        let _ = tup.set(2, Value::isize(st.n_decisions as isize));
+        let _ = tup.set(3, Value::isize(st.n_props as isize));
+        let _ = tup.set(4, Value::isize(st.n_conflicts as isize));
+        let _ = tup.set(5, Value::isize(st.n_solve as isize));
+        let _ = tup.set(6, time);
+        res = tup.into();
+    })
+} -> res);
+
+caml!(ml_batsmt_solver_value_lvl_0, |ptr, lit|, <res>, {
+    with_solver!(solver, ptr, {
+        let lit = lit_of_value(lit);
+        let r = int_of_lbool(solver.api_value_lvl_0(lit));
+        trace!("solver_value_lvl_0", [tr_solver(ptr), tr_lit(lit)], trace::Arg::Int(r as i64));
+        res = Value::isize(r);
+    })
+} -> res);
+
+caml!(ml_batsmt_solver_value, |ptr, lit|, <res>, {
+    with_solver!(solver, ptr, {
+        let lit = lit_of_value(lit);
+        let r = int_of_lbool(solver.api_value(lit));
+        trace!("solver_value", [tr_solver(ptr), tr_lit(lit)], trace::Arg::Int(r as i64));
+        res = Value::isize(r);
+    })
+} -> res);
+
+/// Values of an array of literals, as an array of `Lbool.t`
+caml!(ml_batsmt_solver_values, |ptr, lits|, <res>, {
+    with_solver!(solver, ptr, {
+        let lits: Array = lits.into();
+        let lits: Vec<Lit> = (0 .. lits.len()).map(|i| lit_of_value(lits.get(i).unwrap())).collect();
+        let vals: Vec<isize> = lits.iter().map(|&lit| int_of_lbool(solver.api_value(lit))).collect();
+        trace!("solver_values", [tr_solver(ptr), tr_lits(&lits)],
+            trace::Arg::Ints(vals.iter().map(|&r| r as i64).collect()));
+        // constant constructors of `Lbool.t` are represented by `int_of_lbool`
+        res = vals.iter().map(|&r| Value::isize(r)).collect::<Vec<_>>().to_value();
+    })
+} -> res);
+
+/// The model as a string of bits (see `Solver::api_model_bits`)
+caml!(ml_batsmt_solver_model_bits, |ptr|, <res>, {
+    with_solver!(solver, ptr, {
+        let bits = solver.api_model_bits();
+        trace!("solver_model_bits", [tr_solver(ptr)], trace::Arg::Int(bits.len() as i64));
+        res = Value::new(caml_alloc_initialized_string(bits.len(), bits.as_ptr()));
+    })
+} -> res);
+
+caml!(ml_batsmt_solver_n_proved_lvl_0, |ptr|, <res>, {
+    with_solver!(solver, ptr, {
+        let r = solver.api_proved_at_lvl_0();
+        trace!("solver_n_proved_lvl_0", [tr_solver(ptr)], trace::Arg::Int(r.len() as i64));
+        res = Value::isize(r.len() as isize);
+    })
+} -> res);
+
+caml!(ml_batsmt_solver_proved_lvl_0, |ptr, idx|, <res>, {
+    with_solver!(solver, ptr, {
+        let idx = idx.isize_val();
+        let r = solver.api_proved_at_lvl_0();
+        if idx < 0 || idx as usize >= r.len() {
+            caml_invalid_argument(b"Batsmt.Solver.proved_lvl_0\0".as_ptr())
+        }
+        let lit = Lit::new(r[idx as usize]);
+        trace!("solver_proved_lvl_0", [tr_solver(ptr), trace::Arg::Int(idx as i64)], tr_lit(lit));
+        res = value_of_lit(lit);
+    })
+} -> res);
+
+caml!(ml_batsmt_solver_proved_lvl_0_all, |ptr|, <res>, {
+    with_solver!(solver, ptr, {
+        let lits: Vec<Lit> = solver.api_proved_at_lvl_0().iter().map(|&lit| Lit::new(lit)).collect();
+        trace!("solver_proved_lvl_0_all", [tr_solver(ptr)], tr_lits(&lits));
+        res = lits.iter().map(|&lit| value_of_lit(lit)).collect::<Vec<_>>().to_value();
+    })
+} -> res);
+
+caml!(ml_batsmt_solver_new_proved_lvl_0, |ptr|, <res>, {
+    with_solver!(solver, ptr, {
+        let lits: Vec<Lit> = solver.api_new_proved_at_lvl_0().iter().map(|&lit| Lit::new(lit)).collect();
+        trace!("solver_new_proved_lvl_0", [tr_solver(ptr)], tr_lits(&lits));
+        res = lits.iter().map(|&lit| value_of_lit(lit)).collect::<Vec<_>>().to_value();
+    })
+} -> res);
+
+caml!(ml_batsmt_ty_bool, |ptr|, <res>, {
+    with_ctx!(ctx, ptr, {
+        let t = ctx.api_ty_bool();
+        trace!("ty_bool", [tr_ctx(ptr)], tr_ast(t));
+        res = value_of_ast(t);
+    })
+} -> res);
+
+caml!(ml_batsmt_ty_const, |ptr, s|, <res>, {
+    with_ctx!(ctx, ptr, {
+        let s: Str = s.into();
+        let t = ok_or_fail(ctx.api_ty_const(s.as_str()));
+        trace!("ty_const", [tr_ctx(ptr), trace::Arg::Str(s.as_str())], tr_ast(t));
+        res = value_of_ast(t);
+    })
+} -> res);
+
+caml!(ml_batsmt_term_bool, |ptr, b|, <res>, {
+    with_ctx!(ctx, ptr, {
+        let b = b.isize_val() != 0;
+        let t = ctx.api_bool(b);
+        trace!("term_bool", [tr_ctx(ptr), trace::Arg::Bool(b)], tr_ast(t));
+        res = value_of_ast(t);
+    })
+} -> res);
+
+caml!(ml_batsmt_term_not, |ptr, t|, <res>, {
+    with_ctx!(ctx, ptr, {
+        let t = ast_of_value(ctx, t);
+        let u = ctx.api_not(t);
+        trace!("term_not", [tr_ctx(ptr), tr_ast(t)], tr_ast(u));
+        res = value_of_ast(u);
+    })
+} -> res);
+
+caml!(ml_batsmt_term_const, |ptr, s, args, ret|, <res>, {
+    with_ctx!(ctx, ptr, {
+        let s: Str = s.into();
+        let args: Array = args.into();
+        let mut v_args = Vec::with_capacity(args.len());
+        for i in 0 .. args.len() { v_args.push(ast_of_value(ctx, args.get(i).unwrap())) }
+        let ret = ast_of_value(ctx, ret);
+        let t = ok_or_fail(ctx.api_const(s.as_str(), &v_args, ret));
+        trace!("term_const",
+            [tr_ctx(ptr), trace::Arg::Str(s.as_str()),
+             trace::Arg::Ints(v_args.iter().map(|u| u.idx() as i64).collect()), tr_ast(ret)],
+            tr_ast(t));
+        res = value_of_ast(t);
+    })
+} -> res);
+
+caml!(ml_batsmt_term_cstor, |ptr, s, args, ret|, <res>, {
+    with_ctx!(ctx, ptr, {
+        let s: Str = s.into();
+        let args: Array = args.into();
+        let mut v_args = Vec::with_capacity(args.len());
+        for i in 0 .. args.len() { v_args.push(ast_of_value(ctx, args.get(i).unwrap())) }
+        let ret = ast_of_value(ctx, ret);
+        let t = ok_or_fail(ctx.api_cstor(s.as_str(), &v_args, ret));
+        trace!("term_cstor",
+            [tr_ctx(ptr), trace::Arg::Str(s.as_str()),
+             trace::Arg::Ints(v_args.iter().map(|u| u.idx() as i64).collect()), tr_ast(ret)],
+            tr_ast(t));
+        res = value_of_ast(t);
+    })
+} -> res);
+
+caml!(ml_batsmt_term_eq, |ptr, t1, t2|, <res>, {
+    with_ctx!(ctx, ptr, {
+        let t1 = ast_of_value(ctx, t1);
+        let t2 = ast_of_value(ctx, t2);
+        let t = ok_or_fail(ctx.api_eq(t1, t2));
+        trace!("term_eq", [tr_ctx(ptr), tr_ast(t1), tr_ast(t2)], tr_ast(t));
+        res = value_of_ast(t);
+    })
+} -> res);
+
+caml!(ml_batsmt_term_select, |ptr, c, idx, u|, <res>, {
+    with_ctx!(ctx, ptr, {
+        let c = ast_of_value(ctx, c);
+        let u = ast_of_value(ctx, u);
+        let idx = idx.isize_val() as u32;
+        let r = ok_or_fail(ctx.api_select(c, idx, u));
+        trace!("term_select", [tr_ctx(ptr), tr_ast(c), trace::Arg::Int(idx as i64), tr_ast(u)],
+            tr_ast(r));
+        res = value_of_ast(r);
+    })
+} -> res);
+
+caml!(ml_batsmt_term_app, |ptr, f, args|, <res>, {
+    with_ctx!(ctx, ptr, {
+        let f = ast_of_value(ctx, f);
+        let args: Array = args.into();
+        let mut v_args = Vec::with_capacity(args.len());
+        for i in 0 .. args.len() { v_args.push(ast_of_value(ctx, args.get(i).unwrap())) }
+        let t = ok_or_fail(ctx.api_app(f, &v_args));
+        trace!("term_app",
+            [tr_ctx(ptr), tr_ast(f), trace::Arg::Ints(v_args.iter().map(|u| u.idx() as i64).collect())],
+            tr_ast(t));
+        res = value_of_ast(t);
+    })
+} -> res);
+
+/// `(kind, head, args, name)` where `head` is the function of an application,
+/// or `0`/`1` for booleans, and `name` is the name of a constant.
+caml!(ml_batsmt_term_view, |ptr, t|, <res, args, name>, {
+    with_ctx!(ctx, ptr, {
+        let t = ast_of_value(ctx, t);
+        let k = ctx.api_kind(t);
+        let (head, v_args, s) = match k {
+            AstKind::Bool => (ok_or_fail(ctx.api_get_bool(t)) as isize, &[][..], ""),
+            AstKind::Const | AstKind::Cstor => (0, &[][..], ok_or_fail(ctx.api_const_get_name(t))),
+            AstKind::App | AstKind::Not | AstKind::Selector => {
+                let f = ok_or_fail(ctx.api_app_get_fun(t));
+                (f.idx() as isize, ok_or_fail(ctx.api_app_get_args(t)), "")
+            },
+        };
+        trace!("term_view", [tr_ctx(ptr), tr_ast(t)], trace::Arg::Tuple(vec![
+            trace::Arg::Int(k as u8 as i64), trace::Arg::Int(head as i64),
+            trace::Arg::Ints(v_args.iter().map(|u| u.idx() as i64).collect()),
+            trace::Arg::Str(s),
+        ]));
+        args = v_args.iter().map(|&u| value_of_ast(u)).collect::<Vec<_>>().to_value();
+        name = Value::from(Str::from(s));
+        let mut tup = Tuple::new(4);
+        let _ = tup.set(0, Value::isize(k as u8 as isize));
+        let _ = tup.set(1, Value::isize(head));
+        let _ = tup.set(2, args);
+        let _ = tup.set(3, name);
+        res = tup.into();
+    })
+} -> res);
+
+caml!(ml_batsmt_term_subterms, |ptr, t|, <res>, {
+    with_ctx!(ctx, ptr, {
+        let t = ast_of_value(ctx, t);
+        let sub = ctx.api_subterms(t);
+        trace!("term_subterms", [tr_ctx(ptr), tr_ast(t)],
+            trace::Arg::Ints(sub.iter().map(|u| u.idx() as i64).collect()));
+        res = sub.iter().map(|&u| value_of_ast(u)).collect::<Vec<_>>().to_value();
+    })
+} -> res);
+
+caml!(ml_batsmt_term_import, |ptr, names, nodes|, <res>, {
+    with_ctx!(ctx, ptr, {
+        let names: Array = names.into();
+        let names: Vec<Str> = (0 .. names.len()).map(|i| names.get(i).unwrap().into()).collect();
+        let names: Vec<&str> = names.iter().map(|s| s.as_str()).collect();
+        let nodes: Array = nodes.into();
+        let nodes: Option<Vec<i32>> =
+            (0 .. nodes.len())
+            .map(|i| i32::try_from(nodes.get(i).unwrap().isize_val()).ok())
+            .collect();
+        let nodes = match nodes {
+            Some(v) => v,
+            None => {
+                ocaml::runtime::failwith("batsmt: import: node value out of range");
+                unreachable!()
+            },
+        };
+        let ts = ok_or_fail(ctx.api_import(&names, &nodes));
+        trace!("term_import",
+            [tr_ctx(ptr), trace::Arg::Strs(names.clone()),
+             trace::Arg::Ints(nodes.iter().map(|&x| x as i64).collect())],
+            trace::Arg::Ints(ts.iter().map(|t| t.idx() as i64).collect()));
+        res = ts.iter().map(|&t| value_of_ast(t)).collect::<Vec<_>>().to_value();
+    })
+} -> res);
+
+caml!(ml_batsmt_term_get_select, |ptr, t|, <res>, {
+    println!("there is no select term");
+    /*
+    with_ctx!(ctx, ptr, {
+        let t = ast_of_int(t.isize_val() as u32);
+        let mut tup = Tuple::new(3);
+        match ctx.view_as_constructor(&t) {
+            CView::Select{f, idx, sub} => {
+                let _ = tup.set(0, Value::isize(int_of_ast(*f) as isize));
+                let _ = tup.set(1, Value::isize(idx as isize));
+                let _ = tup.set(2, Value::isize(int_of_ast(*sub) as isize));
+            },
+            _ => panic!("not a select term"),
+        };
+        res = tup.into();
+    })
+    */
+    ocaml::runtime::raise_not_found();
+    res = value::UNIT;
+} -> res);
+
+/// Run a SMT-LIB script on a fresh solver, returning its output.
+caml!(ml_batsmt_smtlib_parse_and_run, |ptr, src|, <res>, {
+    with_ctx!(ctx, ptr, {
+        let src: Str = src.into();
+        let src = src.as_str().to_string();
+        let flags = [ctx.busy() as *const Busy];
+        let out = without_lock(&flags, || smtlib::parse_and_run(ctx, &src));
+        trace!("smtlib_parse_and_run", [tr_ctx(ptr), trace::Arg::Str(&src)],
+            trace::Arg::Str(&out));
+        res = Value::from(Str::from(out.as_str()));
+    })
+} -> res);
+
+caml!(ml_batsmt_term_to_smt2, |ptr, t|, <res>, {
+    with_ctx!(ctx, ptr, {
+        let t = ast_of_value(ctx, t);
+        let mut out = String::new();
+        ok_or_fail(printer::pp_term(ctx, &mut out, t));
+        trace!("term_to_smt2", [tr_ctx(ptr), tr_ast(t)], trace::Arg::Str(&out));
+        res = Value::from(Str::from(out.as_str()));
+    })
+} -> res);
+
+caml!(ml_batsmt_ctx_decls_to_smt2, |ptr|, <res>, {
+    with_ctx!(ctx, ptr, {
+        let mut out = String::new();
+        ok_or_fail(printer::pp_decls(ctx, &mut out));
+        trace!("ctx_decls_to_smt2", [tr_ctx(ptr)], trace::Arg::Str(&out));
+        res = Value::from(Str::from(out.as_str()));
+    })
+} -> res);
+
+caml!(ml_batsmt_ctx_stats, |ptr|, <res>, {
+    with_ctx!(ctx, ptr, {
+        let st = ctx.api_stats();
+        trace!("ctx_stats", [tr_ctx(ptr)], trace::Arg::Unit);
+        // same layout as `Ctx.stats`
+        let mut tup = Tuple::new(4);
+        let _ = tup.set(0, Value::isize(st.n_terms as isize));
+        let _ = tup.set(1, Value::isize(st.n_syms as isize));
+        let _ = tup.set(2, Value::isize(st.n_cstors as isize));
+        let _ = tup.set(3, Value::isize(st.mem_size as isize));
+        res = tup.into();
+    })
+} -> res);
+
+caml!(ml_batsmt_ctx_dump, |ptr|, <res>, {
+    with_ctx!(ctx, ptr, {
+        let mut out = String::new();
+        ctx.api_dump(&mut out);
+        trace!("ctx_dump", [tr_ctx(ptr)], trace::Arg::Unit);
+        res = Value::from(Str::from(out.as_str()));
+    })
+} -> res);
+
+caml!(ml_batsmt_solver_to_smt2, |ptr_s, ptr_ctx|, <res>, {
+    with_ctx!(ctx, ptr_ctx, {
+        with_solver!(solver, ptr_s, {
+            check_ctx(solver, ctx);
+            let mut out = String::new();
+            ok_or_fail(printer::pp_solver(solver, ctx, &mut out));
+            trace!("solver_to_smt2", [tr_solver(ptr_s), tr_ctx(ptr_ctx)], trace::Arg::Str(&out));
+            res = Value::from(Str::from(out.as_str()));
+        })
+    })
+} -> res);
+
+/// Load a DIMACS file, returning the literal of each of its variables.
+caml!(ml_batsmt_solver_load_dimacs, |ptr, file|, <res>, {
+    with_solver!(solver, ptr, {
+        let file: Str = file.into();
+        match std::fs::read_to_string(file.as_str()).map_err(|e| e.to_string())
+            .and_then(|src| dimacs::load(solver, &src).map(|vars| (src, vars)))
+        {
+            Ok((src, vars)) => {
+                // the file might change or be gone by the time of the replay
+                trace!("solver_load_dimacs", [tr_solver(ptr), trace::Arg::Str(&src)],
+                    tr_lits(&vars));
+                res = vars.iter().map(|&lit| value_of_lit(lit)).collect::<Vec<_>>().to_value();
+            },
+            Err(e) => {
+                ocaml::runtime::failwith(&format!("batsmt: load_dimacs: {}", e));
+                res = value::UNIT;
+            },
+        }
+    })
+} -> res);
+
+caml!(ml_batsmt_solver_to_dimacs, |ptr_s, ptr_ctx|, <res>, {
+    with_ctx!(ctx, ptr_ctx, {
+        with_solver!(solver, ptr_s, {
+            check_ctx(solver, ctx);
+            let mut out = String::new();
+            ok_or_fail(dimacs::write(solver, ctx, &mut out));
+            trace!("solver_to_dimacs", [tr_solver(ptr_s), tr_ctx(ptr_ctx)], trace::Arg::Str(&out));
+            res = Value::from(Str::from(out.as_str()));
+        })
+    })
+} -> res);
+
+caml!(ml_batsmt_set_log_lvl, |s|, <res>, {
+    let s: Str = s.into();
+    batsmt_logger::init_from_str(s.as_str());
+    trace!("set_log_lvl", [trace::Arg::Str(s.as_str())], trace::Arg::Unit);
+    res = value::UNIT;
+} -> res);
+
+/// Peak memory of the process, or -1 if unknown.
+caml!(ml_batsmt_peak_mem, |_params|, <res>, {
+    let n = peak_mem().map_or(-1, |n| n as i64);
+    trace!("peak_mem", [], trace::Arg::Int(n));
+    res = Value::isize(n as isize);
+} -> res);
+
+/// Start recording calls into the given file.
+caml!(ml_batsmt_trace_start, |file|, <res>, {
+    let file: Str = file.into();
+    if let Err(e) = trace::start(file.as_str()) {
+        ocaml::runtime::failwith(&format!("batsmt: cannot open trace file: {}", e));
+    }
+    res = value::UNIT;
+} -> res);
+
+caml!(ml_batsmt_trace_stop, |_params|, <res>, {
+    trace::stop();
+    res = value::UNIT;
+} -> res);
+
+fn read_trace(file: Str) -> Result<Vec<replay::Call>, String> {
+    std::fs::read_to_string(file.as_str())
+        .map_err(|e| format!("cannot read {}: {}", file.as_str(), e))
+        .and_then(|src| replay::parse(&src))
+}
+
+/// Replay a trace, failing if a result differs from the recorded one.
+caml!(ml_batsmt_trace_replay, |file|, <res>, {
+    let calls = read_trace(file.into());
+    ocaml::runtime::release_lock();
+    let out = calls.map(|calls| replay::replay(&calls));
+    ocaml::runtime::acquire_lock();
+    match out {
+        Ok(replay::Outcome::Ok{n_checks}) => res = Value::isize(n_checks as isize),
+        Ok(out) => {
+            ocaml::runtime::failwith(&format!("batsmt: replay: {}", out));
+            res = value::UNIT;
+        },
+        Err(e) => {
+            ocaml::runtime::failwith(&format!("batsmt: replay: {}", e));
+            res = value::UNIT;
+        },
+    }
+} -> res);
+
+/// Shrink a failing trace; returns the empty string if it does not fail.
+caml!(ml_batsmt_trace_minimize, |file|, <res>, {
+    let calls = read_trace(file.into());
+    ocaml::runtime::release_lock();
+    let small = calls.map(|calls| replay::minimize(&calls));
+    ocaml::runtime::acquire_lock();
+    match small {
+        Ok(small) => {
+            let mut out = String::new();
+            if let Some(calls) = small { replay::write(&calls, &mut out) }
+            res = Value::from(Str::from(out.as_str()));
+        },
+        Err(e) => {
+            ocaml::runtime::failwith(&format!("batsmt: replay: {}", e));
+            res = value::UNIT;
+        },
+    }
+} -> res);
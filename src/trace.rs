@@ -0,0 +1,262 @@
+//! Recording of calls to the OCaml stubs.
+//!
+//! When enabled, every call is written as one line of the trace:
+//! `name arg… -> result`. Values are integers, `true`/`false`, floats,
+//! strings in double quotes (with `\\`, `\"` and `\n` escaped), arrays
+//! `[x y …]` of integers or strings, tuples `(x y …)`, `()`, and handles
+//! `c<n>`, `s<n>`, `j<n>` for contexts, solvers and jobs. Terms and literals
+//! are recorded as their integer.
+//! Handles are numbered by a counter, so that they are never reused even
+//! when a new value is allocated at the address of a deleted one.
+//! Loading a DIMACS file records the contents of the file, not its name.
+//!
+//! Tracing can be started with `start`, or by setting `BATSMT_TRACE`
+//! to a file name before creating the first context.
+
+use {
+    std::{
+        fs::File, fmt::Write as FmtWrite,
+        io::{self, Write, LineWriter},
+        sync::{Mutex, Once, atomic::{AtomicBool, AtomicUsize, Ordering}},
+    },
+};
+
+/// A recorded value.
+pub enum Arg<'a> {
+    Unit,
+    Int(i64),
+    Bool(bool),
+    Float(f64),
+    Str(&'a str),
+    Ints(Vec<i64>),
+    Strs(Vec<&'a str>),
+    Tuple(Vec<Arg<'a>>), // not empty
+    Ctx(usize), // handle of the context, see `new_handle`
+    Solver(usize),
+    Job(usize),
+}
+
+struct Recorder {
+    out: LineWriter<File>,
+    line: String,
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static RECORDER: Mutex<Option<Recorder>> = Mutex::new(None);
+static FROM_ENV: Once = Once::new();
+static NEXT_HANDLE: AtomicUsize = AtomicUsize::new(1);
+
+/// Is tracing enabled?
+#[inline]
+pub fn enabled() -> bool { ENABLED.load(Ordering::Relaxed) }
+
+/// Start recording into `file`, replacing the current trace if any.
+///
+/// Contexts and solvers created before this point keep their handle,
+/// but the trace cannot be replayed.
+pub fn start(file: &str) -> io::Result<()> {
+    let out = LineWriter::new(File::create(file)?);
+    let r = Recorder{out, line: String::new()};
+    *RECORDER.lock().unwrap() = Some(r);
+    ENABLED.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Stop recording, and close the trace.
+pub fn stop() {
+    ENABLED.store(false, Ordering::Relaxed);
+    if let Some(mut r) = RECORDER.lock().unwrap().take() {
+        let _ = r.out.flush();
+    }
+}
+
+/// Start recording if `BATSMT_TRACE` is set. Only the first call does something.
+pub fn init_from_env() {
+    FROM_ENV.call_once(|| {
+        if let Ok(file) = std::env::var("BATSMT_TRACE") {
+            if let Err(e) = start(&file) {
+                eprintln!("batsmt: cannot open trace file {:?}: {}", file, e);
+            }
+        }
+    })
+}
+
+/// A fresh handle for a context, solver or job being created.
+///
+/// Every value gets one, whether tracing is enabled or not, so that
+/// values created before `start` can still be named in the trace.
+pub fn new_handle() -> usize {
+    NEXT_HANDLE.fetch_add(1, Ordering::Relaxed)
+}
+
+impl Recorder {
+    fn handle(&mut self, prefix: char, id: usize) {
+        let _ = write!(self.line, "{}{}", prefix, id);
+    }
+
+    fn str(&mut self, s: &str) {
+        self.line.push('"');
+        for c in s.chars() {
+            match c {
+                '"' => self.line.push_str("\\\""),
+                '\\' => self.line.push_str("\\\\"),
+                '\n' => self.line.push_str("\\n"),
+                c => self.line.push(c),
+            }
+        }
+        self.line.push('"');
+    }
+
+    fn arg(&mut self, a: &Arg) {
+        match a {
+            Arg::Unit => self.line.push_str("()"),
+            Arg::Int(i) => { let _ = write!(self.line, "{}", i); },
+            Arg::Bool(b) => { let _ = write!(self.line, "{}", b); },
+            Arg::Float(f) => { let _ = write!(self.line, "{:?}", f); },
+            Arg::Str(s) => self.str(s),
+            Arg::Ints(v) => {
+                self.line.push('[');
+                for (i, x) in v.iter().enumerate() {
+                    if i > 0 { self.line.push(' ') }
+                    let _ = write!(self.line, "{}", x);
+                }
+                self.line.push(']');
+            },
+            Arg::Strs(v) => {
+                self.line.push('[');
+                for (i, s) in v.iter().enumerate() {
+                    if i > 0 { self.line.push(' ') }
+                    self.str(s);
+                }
+                self.line.push(']');
+            },
+            Arg::Tuple(v) => {
+                self.line.push('(');
+                for (i, a) in v.iter().enumerate() {
+                    if i > 0 { self.line.push(' ') }
+                    self.arg(a);
+                }
+                self.line.push(')');
+            },
+            Arg::Ctx(p) => self.handle('c', *p),
+            Arg::Solver(p) => self.handle('s', *p),
+            Arg::Job(p) => self.handle('j', *p),
+        }
+    }
+}
+
+/// Record a call to `name`.
+///
+/// If the trace cannot be written, the error is reported once on stderr
+/// and tracing is stopped.
+pub fn record(name: &str, args: &[Arg], res: Arg) {
+    let mut guard = RECORDER.lock().unwrap();
+    let r = match guard.as_mut() { Some(r) => r, None => return };
+    r.line.clear();
+    r.line.push_str(name);
+    for a in args.iter() {
+        r.line.push(' ');
+        r.arg(a);
+    }
+    r.line.push_str(" -> ");
+    r.arg(&res);
+    r.line.push('\n');
+    if let Err(e) = r.out.write_all(r.line.as_bytes()) {
+        eprintln!("batsmt: cannot write trace, tracing stopped: {}", e);
+        ENABLED.store(false, Ordering::Relaxed);
+        *guard = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ctx::Ctx, replay, portfolio,
+        solver::{Solver, Options, Lit},
+    };
+
+    fn t(u: crate::Term) -> Arg<'static> { Arg::Int(u.idx() as i64) }
+    fn l(lit: Lit) -> Arg<'static> { Arg::Int(lit.to_int() as i64) }
+
+    // run a few calls, recording them as the stubs do. Returns the
+    // handles of the context and solver.
+    fn record_session(file: &str) -> (usize, usize) {
+        start(file).unwrap();
+        let mut ctx = Ctx::new();
+        let c = new_handle();
+        record("ctx_new", &[], Arg::Ctx(c));
+        let bool_ = ctx.api_ty_bool();
+        record("ty_bool", &[Arg::Ctx(c)], t(bool_));
+        let f = ctx.api_const("f", &[bool_], bool_).unwrap();
+        record("term_const", &[Arg::Ctx(c), Arg::Str("f"), Arg::Ints(vec![bool_.idx() as i64]), t(bool_)], t(f));
+        let a = ctx.api_const("a", &[], bool_).unwrap();
+        record("term_const", &[Arg::Ctx(c), Arg::Str("a"), Arg::Ints(vec![]), t(bool_)], t(a));
+        let fa = ctx.api_app(f, &[a]).unwrap();
+        record("term_app", &[Arg::Ctx(c), t(f), Arg::Ints(vec![a.idx() as i64])], t(fa));
+
+        // the replay must map the results of the queries to use them
+        let g = ctx.api_app_get_fun(fa).unwrap();
+        let a0 = ctx.api_app_get_args(fa).unwrap()[0];
+        let k = ctx.api_kind(fa) as u8 as i64;
+        record("term_view", &[Arg::Ctx(c), t(fa)],
+            Arg::Tuple(vec![Arg::Int(k), t(g), Arg::Ints(vec![a0.idx() as i64]), Arg::Str("")]));
+        let sub = ctx.api_subterms(fa);
+        record("term_subterms", &[Arg::Ctx(c), t(fa)],
+            Arg::Ints(sub.iter().map(|u| u.idx() as i64).collect()));
+        let k = ctx.api_kind(g) as u8 as i64;
+        record("term_view", &[Arg::Ctx(c), t(g)],
+            Arg::Tuple(vec![Arg::Int(k), Arg::Int(0), Arg::Ints(vec![]), Arg::Str("f")]));
+        let ga = ctx.api_app(g, &[a0]).unwrap();
+        record("term_app", &[Arg::Ctx(c), t(g), Arg::Ints(vec![a0.idx() as i64])], t(ga));
+
+        let mut s = Solver::new_with(&mut ctx, Options{keep_clauses: true, ..Options::default()});
+        let h = new_handle();
+        record("solver_new", &[Arg::Ctx(c), Arg::Int(0), Arg::Bool(false), Arg::Bool(true)],
+            Arg::Solver(h));
+        let lit = s.api_make_term_lit(&mut ctx, ga);
+        record("solver_new_term_lit", &[Arg::Solver(h), Arg::Ctx(c), t(ga)], l(lit));
+        s.api_add_clause_from(&[lit]).unwrap();
+        record("solver_add_clause_a", &[Arg::Solver(h), Arg::Ints(vec![lit.to_int() as i64])], Arg::Unit);
+
+        let p = portfolio::Problem::new(&s, &ctx, &[]).unwrap();
+        let ans = portfolio::solve(p, &[1, 2]).unwrap();
+        record("solver_solve_portfolio", &[Arg::Solver(h), Arg::Ctx(c), Arg::Ints(vec![1, 2])],
+            Arg::Bool(ans.sat));
+
+        let neg = Lit::unsafe_from_int(-lit.to_int());
+        s.api_add_clause_from(&[neg]).unwrap();
+        record("solver_add_clause_a", &[Arg::Solver(h), Arg::Ints(vec![neg.to_int() as i64])], Arg::Unit);
+        let r = s.api_solve(&mut ctx);
+        record("solver_solve", &[Arg::Solver(h), Arg::Ctx(c)], Arg::Bool(r));
+        stop();
+        (c, h)
+    }
+
+    #[test]
+    fn record_then_replay() {
+        let file = std::env::temp_dir().join(format!("batsmt-trace-{}", std::process::id()));
+        let file = file.to_str().unwrap();
+        let (c, h) = record_session(file);
+        let src = std::fs::read_to_string(file).unwrap();
+        let _ = std::fs::remove_file(file);
+
+        let calls = replay::parse(&src).unwrap();
+        match replay::replay(&calls) {
+            replay::Outcome::Ok{n_checks} => assert_eq!(n_checks, 4),
+            out => panic!("{}", out),
+        }
+        assert!(replay::minimize(&calls).is_none());
+
+        // a wrong result is found, and kept by minimization
+        let solve = format!("solver_solve s{} c{} -> ", h, c);
+        let bad = src.replace(&format!("{}false", solve), &format!("{}true", solve));
+        assert_ne!(bad, src);
+        let calls = replay::parse(&bad).unwrap();
+        let out = replay::replay(&calls);
+        assert!(out.is_failure(), "{}", out);
+        let small = replay::minimize(&calls).unwrap();
+        assert!(small.len() < calls.len());
+        assert_eq!(small.last().unwrap().name, "solver_solve");
+    }
+}
@@ -0,0 +1,98 @@
+/* Test of the C API, see `make test-capi`. */
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <stdint.h>
+#include "batsmt.h"
+
+static int n_failed = 0;
+
+/* check that `call` returns `code` */
+#define EXPECT(call, code) do { \
+    int r_ = (call); \
+    if (r_ != (code)) { \
+      fprintf(stderr, "%s:%d: %s returned %d, expected %d (%s)\n", \
+              __FILE__, __LINE__, #call, r_, (code), batsmt_last_error()); \
+      n_failed++; \
+    } \
+  } while (0)
+
+#define OK(call) EXPECT(call, BATSMT_OK)
+
+int main(void) {
+  batsmt_ctx *c = batsmt_ctx_new();
+  if (!c) { fprintf(stderr, "cannot create a context\n"); return 1; }
+
+  batsmt_term bool_, p, q, not_p;
+  OK(batsmt_ty_bool(c, &bool_));
+  OK(batsmt_term_const(c, "p", NULL, 0, bool_, &p));
+  OK(batsmt_term_const(c, "q", NULL, 0, bool_, &q));
+  OK(batsmt_term_not(c, p, &not_p));
+
+  /* terms that do not exist */
+  batsmt_term t;
+  EXPECT(batsmt_term_not(c, 1000000, &t), BATSMT_ERR_INVALID);
+  EXPECT(batsmt_term_eq(c, p, UINT32_MAX, &t), BATSMT_ERR_INVALID);
+  batsmt_term bad_args[1] = { 1000000 };
+  EXPECT(batsmt_term_const(c, "f", bad_args, 1, bool_, &t), BATSMT_ERR_INVALID);
+  EXPECT(batsmt_term_app(c, p, bad_args, 1, &t), BATSMT_ERR_INVALID);
+
+  /* redeclarations */
+  batsmt_term same;
+  OK(batsmt_term_const(c, "p", NULL, 0, bool_, &same));
+  if (same != p) { fprintf(stderr, "expected the same p\n"); n_failed++; }
+  EXPECT(batsmt_term_const(c, "p", &bool_, 1, bool_, &t), BATSMT_ERR_INVALID);
+  EXPECT(batsmt_term_cstor(c, "p", NULL, 0, bool_, &t), BATSMT_ERR_INVALID);
+
+  batsmt_solver *s;
+  OK(batsmt_solver_new(c, &s));
+  batsmt_lit lp, lq;
+  OK(batsmt_solver_new_term_lit(s, c, p, &lp));
+  OK(batsmt_solver_new_term_lit(s, c, q, &lq));
+  EXPECT(batsmt_solver_new_term_lit(s, c, 1000000, &lp), BATSMT_ERR_INVALID);
+
+  /* literals that do not exist */
+  batsmt_lit zero[1] = { 0 };
+  batsmt_lit big[1] = { 1000 };
+  batsmt_lit min[1] = { INT32_MIN };
+  EXPECT(batsmt_solver_add_clause(s, zero, 1), BATSMT_ERR_INVALID);
+  EXPECT(batsmt_solver_add_clause(s, big, 1), BATSMT_ERR_INVALID);
+  EXPECT(batsmt_solver_add_clause(s, min, 1), BATSMT_ERR_INVALID);
+  EXPECT(batsmt_solver_add_clauses(s, big, 1), BATSMT_ERR_INVALID);
+  EXPECT(batsmt_solver_add_assumption(s, 0), BATSMT_ERR_INVALID);
+  EXPECT(batsmt_solver_add_assumption(s, -1000), BATSMT_ERR_INVALID);
+
+  /* p ∨ q, ¬p ∨ q */
+  batsmt_lit cs[6] = { lp, lq, 0, -lp, lq, 0 };
+  OK(batsmt_solver_add_clauses(s, cs, 6));
+
+  bool sat;
+  OK(batsmt_solver_solve(s, c, &sat));
+  if (!sat) { fprintf(stderr, "expected sat\n"); n_failed++; }
+  int v;
+  OK(batsmt_solver_value(s, lq, &v));
+  if (v != BATSMT_TRUE) { fprintf(stderr, "expected q to be true\n"); n_failed++; }
+  EXPECT(batsmt_solver_value(s, 1000, &v), BATSMT_ERR_INVALID);
+
+  /* unsat under the assumption ¬q */
+  OK(batsmt_solver_add_assumption(s, -lq));
+  OK(batsmt_solver_solve(s, c, &sat));
+  if (sat) { fprintf(stderr, "expected unsat\n"); n_failed++; }
+  bool in_core;
+  OK(batsmt_solver_unsat_core_contains(s, -lq, &in_core));
+  EXPECT(batsmt_solver_unsat_core_contains(s, 0, &in_core), BATSMT_ERR_INVALID);
+
+  /* the context outlives its solvers */
+  EXPECT(batsmt_ctx_free(c), BATSMT_ERR_HAS_SOLVERS);
+  OK(batsmt_solver_free(s));
+  OK(batsmt_ctx_free(c));
+
+  EXPECT(batsmt_ctx_free(NULL), BATSMT_ERR_NULL);
+
+  if (n_failed > 0) {
+    fprintf(stderr, "%d checks failed\n", n_failed);
+    return 1;
+  }
+  printf("C API: ok\n");
+  return 0;
+}